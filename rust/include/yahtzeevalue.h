@@ -7,13 +7,49 @@ struct yahtzeevalue_error {
     int code;
 };
 
+struct yahtzeevalue_ranked_action {
+    int action;
+    int points;
+    int next_state;
+    /* points plus the table value of next_state, including the upper section
+       par of the rules for an expectation table; NaN if unreachable. */
+    double total;
+};
+
 void yahtzeevalue_init();
 
 yahtzeevalue_t *yahtzeevalue_load(const char *root, struct yahtzeevalue_error *);
 void yahtzeevalue_unload(yahtzeevalue_t *, struct yahtzeevalue_error *);
+/* Checks the table against its checksum, which loading does not, giving
+   error code 9 (Corrupt) if they differ. Reads the whole table. */
+int yahtzeevalue_verify(yahtzeevalue_t *, struct yahtzeevalue_error *);
 double yahtzeevalue_lookup(yahtzeevalue_t *, int state, struct yahtzeevalue_error *);
-int yahtzeevalue_best_action(yahtzeevalue_t *, int state, int histogram, struct yahtzeevalue_error *);
+/* rerolls is the number of rerolls left in the turn, from 0 to those of the
+   state, or else error code 2 (Range). Under rules that save rerolls, the
+   rows are valued with the rerolls saved by stopping. */
+int yahtzeevalue_best_action(yahtzeevalue_t *, int state, int histogram, int rerolls, struct yahtzeevalue_error *);
 int yahtzeevalue_keep_first(yahtzeevalue_t *, int state, int histogram, struct yahtzeevalue_error *);
 int yahtzeevalue_keep_second(yahtzeevalue_t *, int state, int histogram, struct yahtzeevalue_error *);
+int yahtzeevalue_keep(yahtzeevalue_t *, int state, int histogram, int rerolls, struct yahtzeevalue_error *);
+/* Returns the number of rows, best first; writes at most capacity of them to out,
+   which may be NULL if capacity is 0. */
+int yahtzeevalue_rank_actions(yahtzeevalue_t *, int state, int histogram, int rerolls, struct yahtzeevalue_ranked_action *out, int capacity, struct yahtzeevalue_error *);
+
+typedef void yahtzeevalue_game_t;
+
+/* A game of the named rules, e.g. "yatzy". Moves that are not allowed fail
+   with error codes 10 to 13 and leave the game as it was. */
+yahtzeevalue_game_t *yahtzeevalue_game_new(const char *rules, int player_count, struct yahtzeevalue_error *);
+void yahtzeevalue_game_free(yahtzeevalue_game_t *, struct yahtzeevalue_error *);
+int yahtzeevalue_game_roll(yahtzeevalue_game_t *, int histogram, struct yahtzeevalue_error *);
+int yahtzeevalue_game_keep(yahtzeevalue_game_t *, int histogram, struct yahtzeevalue_error *);
+/* action is a score sheet position as returned by yahtzeevalue_best_action.
+   Returns the points scored. */
+int yahtzeevalue_game_choose(yahtzeevalue_game_t *, int action, struct yahtzeevalue_error *);
+int yahtzeevalue_game_done(yahtzeevalue_game_t *, struct yahtzeevalue_error *);
+int yahtzeevalue_game_player(yahtzeevalue_game_t *, struct yahtzeevalue_error *);
+int yahtzeevalue_game_state(yahtzeevalue_game_t *, struct yahtzeevalue_error *);
+int yahtzeevalue_game_rerolls(yahtzeevalue_game_t *, struct yahtzeevalue_error *);
+int yahtzeevalue_game_points(yahtzeevalue_game_t *, int player, struct yahtzeevalue_error *);
 
 void yahtzeevalue_free(char *);
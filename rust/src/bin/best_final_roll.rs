@@ -3,13 +3,14 @@ use yahtzeevalue::*;
 use yahtzeevalue::constants::*;
 
 fn main() {
+    let rules = RuleSet::super_yahtzee();
     for i in 0..18 {
-        let s = State::decode(0x3FFFF & !(1 << i));
+        let s = State::decode(&rules, 0x3FFFF & !(1 << i));
         let mut best_action = Action::Side(SIDES);
         let mut best_points = 0;
         let mut best_outcome = None;
-        for o in outcomes() {
-            actions(s, o, |action, _next_state, points| {
+        for o in outcomes(&rules) {
+            actions(&rules, s, o, |action, _next_state, points| {
                 if points > best_points {
                     best_action = action;
                     best_points = points;
@@ -1,35 +1,32 @@
-extern crate byteorder;
+//! Write a compressed table with only the layers of states chosen by
+//! `precompute_state`, e.g. state_value.compressed.bin. `Store` reads it
+//! and computes the other states when needed.
+//!
+//! Usage: compress [RULES]
 
-use std::{io, fs};
-use std::io::BufRead;
-use byteorder::{LittleEndian, ReadBytesExt};
+use std::env;
 
 extern crate yahtzeevalue;
 use yahtzeevalue::*;
-use yahtzeevalue::constants::*;
 
-fn read_state_value() -> io::Result<Vec<f64>> {
-    let file = fs::File::open("state_value.bin")?;
-    let size = file.metadata()?.len() as usize;
-    let mut reader = io::BufReader::new(file);
-    let mut state_value = vec![0f64; size / 8];
-    for x in state_value.iter_mut() {
-        *x = reader.read_f64::<LittleEndian>()?;
-    }
-    Ok(state_value)
-}
-
-fn precompute_state(state: State) -> bool {
-    let t = state.turn_count();
-    t % 2 == 0 && t != 8 && t != 10
+fn read_state_value(rules: &RuleSet) -> Result<Vec<f64>> {
+    let store = Store::with_rules(&rules.table_path(), rules.clone())?;
+    Ok((0..store.len()).map(|i| store.get(i).unwrap_or(f64::NAN)).collect())
 }
 
 fn main() {
-    let all_state_value = read_state_value().expect("Failed to read state value");
-    let mut state_value_map = Vec::new();
-    for (i, &v) in all_state_value.iter().enumerate() {
-        if precompute_state(State::decode(i as u32)) {
-            state_value_map.push((i, v));
+    let rules = match env::args().nth(1) {
+        Some(name) => RuleSet::by_name(&name).expect("Unknown rule set"),
+        None => RuleSet::super_yahtzee(),
+    };
+    let all_state_value = read_state_value(&rules).expect("Failed to read state value");
+    let mut stored = 0;
+    for i in 0..all_state_value.len() {
+        if precompute_state(&rules, State::decode(&rules, i as u32)) {
+            stored += 1;
         }
     }
+    let path = rules.table_path().replace(".bin", ".compressed.bin");
+    write_compressed(&path, &rules, &all_state_value).expect("Writing failed");
+    eprintln!("Wrote {} of {} states to {}", stored, all_state_value.len(), path);
 }
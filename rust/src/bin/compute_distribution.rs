@@ -0,0 +1,27 @@
+//! Compute the distribution of the final score from every state when
+//! playing to maximize expectation, using the table written by
+//! compute_state_value. States are computed a layer at a time and
+//! written when done, so memory holds two layers of distributions.
+//!
+//! Usage: compute_distribution RULES, e.g. "yatzy-forced".
+extern crate yahtzeevalue;
+
+use std::env;
+
+use yahtzeevalue::{compute_distribution_file, RuleSet, Store};
+
+fn main() {
+    let rules = match env::args().nth(1) {
+        Some(name) => RuleSet::by_name(&name).expect("Unknown rule set"),
+        None => RuleSet::super_yahtzee(),
+    };
+    let state_value = Store::with_rules(&rules.table_path(), rules.clone()).expect("Failed to read state value");
+    let path = rules.distribution_path();
+    compute_distribution_file(&rules, &mut |i| state_value.get(i).unwrap_or(f64::NAN), &path, |i, n| {
+        if i == 0 {
+            eprintln!("Compute distribution of {} states", n);
+        } else if i.is_multiple_of(1 << 16) {
+            eprintln!("{:8}/{}", i, n);
+        }
+    }).expect("Writing failed");
+}
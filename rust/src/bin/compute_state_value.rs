@@ -1,39 +1,79 @@
 //! Compute expected value for the optimal strategy in every state of
 //! Super Yahtzee. Takes between 100 and 140 minutes to compute.
+//!
+//! Usage: compute_state_value [--threads N] [--reachable] [--objective NAME] [RULES], where
+//! RULES is the name of a rule set, e.g. "yahtzee" or "yatzy-forced" for
+//! rows filled in order. Defaults to "super-yahtzee" on all available cores.
+//! With --reachable, only states reachable from the initial state are
+//! evaluated, and the other states are stored as NaN.
+//!
+//! With --objective NAME, e.g. "exp0.01" or "meansd0.5", the states are
+//! valued by a risk-adjusted objective instead of expectation, and the
+//! table is written to e.g. state_value_yatzy_exp0.01.bin.
+//!
+//! The table starts with a header recording the rules, the objective and
+//! a checksum of the values. `Store` checks the rules and objective when
+//! opening it, and `Store::verify` the checksum.
+//!
+//! A checkpoint is written to e.g. state_value.ckpt after each layer of
+//! states. With --resume, the computation continues from the checkpoint,
+//! which must be of the same rules and objective.
 extern crate yahtzeevalue;
-extern crate byteorder;
 
-use std::{io, fs};
+use std::{fs, env, thread};
 
-use byteorder::{LittleEndian, WriteBytesExt};
-
-use yahtzeevalue::compute_state_value;
+use yahtzeevalue::{compute_state_value_checkpointed, write_table, Encoding, Objective, RuleSet};
 
 fn main() {
-    let file = fs::File::create("state_value.tmp").expect("Could not open file");
-    let state_value = compute_state_value(|i, n| {
-        // 8 dots in a cluster, 32 dots in a line, BONUS_LIMIT lines
+    let mut rules = RuleSet::super_yahtzee();
+    let mut threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut reachable = false;
+    let mut resume = false;
+    let mut objective = Objective::Expectation;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--threads" {
+            threads = args.next().and_then(|n| n.parse().ok()).expect("--threads needs a number");
+        } else if arg == "--reachable" {
+            reachable = true;
+        } else if arg == "--resume" {
+            resume = true;
+        } else if arg == "--objective" {
+            objective = args.next().and_then(|n| Objective::by_name(&n)).expect("Unknown objective");
+        } else {
+            rules = RuleSet::by_name(&arg).expect("Unknown rule set");
+        }
+    }
+    let path = objective.table_path(&rules);
+    let tmp_path = path.replace(".bin", ".tmp");
+    let checkpoint_path = path.replace(".bin", ".ckpt");
+    if resume && fs::metadata(&checkpoint_path).is_err() {
+        eprintln!("No checkpoint in {}, starting from scratch", checkpoint_path);
+        resume = false;
+    }
+    let progress = |i: usize, n: usize| {
+        // 8 dots in a cluster, 32 dots in a line, bonus_limit lines
         // each line represents 2**18
         // each dot represents 2**13
         if i == 0 {
             eprintln!("Compute value of {} states", n);
         }
-        if i != n && (i == 0 || i % (1 << 13) != 0) {
+        if i != n && (i == 0 || !i.is_multiple_of(1 << 13)) {
             return;
         }
-        eprint!("{}", ".");
-        if i == n || i % (1 << 16) == 0 {
+        eprint!(".");
+        if i == n || i.is_multiple_of(1 << 16) {
             eprint!(" ");
-            if i == n || i % (1 << 18) == 0 {
-                eprint!("{:8}/{}\n", i, n);
+            if i == n || i.is_multiple_of(1 << 18) {
+                eprintln!("{:8}/{}", i, n);
             }
         }
-    });
-    {
-        let mut writer = io::BufWriter::new(file);
-        for x in state_value.iter() {
-            writer.write_f64::<LittleEndian>(*x).expect("Writing failed");
-        }
+    };
+    let state_value = compute_state_value_checkpointed(&rules, objective, threads, reachable, &checkpoint_path, resume, progress)
+        .expect("Failed to read or write checkpoint");
+    write_table(&tmp_path, &rules, objective, Encoding::F64, &state_value).expect("Writing failed");
+    fs::rename(&tmp_path, &path).expect("Failed to rename temporary file");
+    if fs::metadata(&checkpoint_path).is_ok() {
+        fs::remove_file(&checkpoint_path).expect("Failed to remove checkpoint");
     }
-    fs::rename("state_value.tmp", "state_value.bin").expect("Failed to rename state_value.tmp");
 }
@@ -0,0 +1,24 @@
+//! Compute the best probability of reaching every target score from
+//! every state. States are computed a layer at a time and written when
+//! done, so memory holds two layers of target values.
+//!
+//! Usage: compute_target_value RULES, e.g. "yatzy-forced".
+extern crate yahtzeevalue;
+
+use std::env;
+
+use yahtzeevalue::{compute_target_value_file, RuleSet};
+
+fn main() {
+    let rules = match env::args().nth(1) {
+        Some(name) => RuleSet::by_name(&name).expect("Unknown rule set"),
+        None => RuleSet::super_yahtzee(),
+    };
+    compute_target_value_file(&rules, &rules.target_path(), |i, n| {
+        if i == 0 {
+            eprintln!("Compute target values of {} states", n);
+        } else if i.is_multiple_of(1 << 16) {
+            eprintln!("{:8}/{}", i, n);
+        }
+    }).expect("Writing failed");
+}
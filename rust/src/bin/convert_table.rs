@@ -0,0 +1,24 @@
+//! Convert the table written by compute_state_value to a smaller
+//! encoding, e.g. state_value_yatzy.bin to state_value_yatzy.u16.bin.
+//!
+//! Usage: convert_table ENCODING [RULES], where ENCODING is "f32" or
+//! "u16" (or "f64" to convert back). Use verify_table to see how the
+//! lower precision changes decisions.
+extern crate yahtzeevalue;
+
+use std::env;
+
+use yahtzeevalue::{write_table, Encoding, RuleSet, Store};
+
+fn main() {
+    let encoding = env::args().nth(1).and_then(|n| Encoding::by_name(&n)).expect("Usage: convert_table f32|u16 [RULES]");
+    let rules = match env::args().nth(2) {
+        Some(name) => RuleSet::by_name(&name).expect("Unknown rule set"),
+        None => RuleSet::super_yahtzee(),
+    };
+    let store = Store::with_rules(&rules.table_path(), rules.clone()).expect("Failed to read state value");
+    let state_value: Vec<f64> = (0..store.len()).map(|i| store.get(i).unwrap_or(f64::NAN)).collect();
+    let path = encoding.table_path(&rules);
+    write_table(&path, &rules, store.objective(), encoding, &state_value).expect("Writing failed");
+    eprintln!("Wrote {} states to {}", state_value.len(), path);
+}
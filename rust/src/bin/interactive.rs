@@ -7,15 +7,17 @@
 // Initial roll: "I would keep 56 to go for two pairs"
 // Final roll: "I would take the obvious choice: ..." (i.e. the non-Chance one with highest score)
 // List other actions and their expectations rounded to integers (or a couple decimals if some are close)
-use std::io;
+use std::{io, env, process};
 use std::io::BufRead;
 
+extern crate rand;
+
 extern crate yahtzeevalue;
 use yahtzeevalue::*;
 use yahtzeevalue::constants::*;
 
-fn parse_outcome(line: &str) -> Option<Outcome> {
-    if line.len() != DICE_COUNT {
+fn parse_outcome(rules: &RuleSet, line: &str) -> Option<Outcome> {
+    if line.len() != rules.dice_count {
         return None;
     }
     let mut outcome = Outcome::empty();
@@ -38,7 +40,7 @@ struct Tokenizer<R: io::Read> {
     word: usize,
 }
 
-impl <R: io::Read> Tokenizer<R> {
+impl<R: io::Read> Tokenizer<R> {
     fn new(inner: R) -> Self {
         Tokenizer {
             reader: io::BufReader::new(inner),
@@ -60,30 +62,32 @@ impl <R: io::Read> Tokenizer<R> {
     fn next<O, F: FnMut(&str) -> Option<O>>(&mut self, prompt: &str, mut parser: F) -> O {
         let mut p = prompt;
         loop {
-            while None == self.peek_word() {
+            while self.peek_word().is_none() {
                 println!("{}", p);
                 p = "I did not understand that.";
                 self.line.clear();
-                self.reader.read_line(&mut self.line).unwrap();
+                if self.reader.read_line(&mut self.line).unwrap() == 0 {
+                    process::exit(0);
+                }
                 self.word = 0;
             }
             let r = self.next_word().unwrap();
-            match parser(r) {
-                Some(o) => return o,
-                None => (),
-            };
+            if let Some(o) = parser(r) {
+                return o;
+            }
         }
     }
 }
 
-struct Player {
+/// A player's score sheet as the user has entered it.
+struct Sheet {
     state: u32,
     points: u32,
 }
 
-impl Player {
+impl Sheet {
     fn new() -> Self {
-        Player {
+        Sheet {
             state: 0,
             points: 0,
         }
@@ -98,13 +102,14 @@ enum CommandWord {
     Help,
     Score,
     Bonus,
+    Target,
+    Bot,
 }
 
-fn parse_command_word(w: &str, game: &Game) -> Option<CommandWord> {
-    match parse_outcome(w) {
-        Some(o) => return Some(CommandWord::Roll(o)),
-        None => (),
-    };
+fn parse_command_word(w: &str, session: &Session) -> Option<CommandWord> {
+    if let Some(o) = parse_outcome(&session.rules, w) {
+        return Some(CommandWord::Roll(o));
+    }
     if w == "players" {
         return Some(CommandWord::Players);
     }
@@ -117,15 +122,21 @@ fn parse_command_word(w: &str, game: &Game) -> Option<CommandWord> {
     if w == "bonus" {
         return Some(CommandWord::Bonus);
     }
-    for (i, (_value, action, _state, _points)) in game.choices.iter().enumerate() {
-        if w == action.shorthand() {
+    if w == "target" {
+        return Some(CommandWord::Target);
+    }
+    if w == "bot" {
+        return Some(CommandWord::Bot);
+    }
+    for (i, (_value, action, _state, _points)) in session.choices.iter().enumerate() {
+        if w == action.shorthand(&session.rules) {
             return Some(CommandWord::Action(i));
         }
     }
     if w == "help" {
         return Some(CommandWord::Help);
     }
-    return None
+    None
 }
 
 enum Command {
@@ -136,33 +147,43 @@ enum Command {
     Help,
     Score(i32),
     Bonus(i32),
+    Target(i32),
+    Bot,
 }
 
-struct Game {
-    players: Vec<Player>,
+/// The game being advised on. Unlike the library `Game`, it follows the
+/// moves the user reports, and the players, turns and points may be
+/// changed at will.
+struct Session {
+    rules: RuleSet,
+    players: Vec<Sheet>,
     player_count: usize,
     player_index: usize,
     choices: Vec<(f64, Action, u32, u32)>,
     roll_index: usize,
     prompt: String,
+    /// Final score to play for instead of expectation.
+    target: Option<i32>,
 }
 
-fn parse_command<R: io::Read>(reader: &mut Tokenizer<R>, game: &mut Game) -> Command {
-    match reader.next(&game.prompt, |w| parse_command_word(w, game)) {
+fn parse_command<R: io::Read>(reader: &mut Tokenizer<R>, session: &mut Session) -> Command {
+    match reader.next(&session.prompt, |w| parse_command_word(w, session)) {
         CommandWord::Players => Command::Players(reader.next("New player count:", |w| w.parse::<usize>().ok())),
         CommandWord::Player => Command::Player(reader.next("Whose turn is it?", |w| w.parse::<usize>().ok())),
         CommandWord::Roll(o) => Command::Roll(o),
         CommandWord::Action(i) => {
-            let (_value, _action, state, points) = &game.choices[i];
-            Command::Action(*state, *points)
+            let (_, _, state, points) = session.choices[i];
+            Command::Action(state, points)
         },
         CommandWord::Help => Command::Help,
         CommandWord::Score => Command::Score(reader.next("Points to add/subtract:", |w| w.parse::<i32>().ok())),
         CommandWord::Bonus => Command::Bonus(reader.next("Points to add/subtract:", |w| w.parse::<i32>().ok())),
+        CommandWord::Target => Command::Target(reader.next("Final score to play for:", |w| w.parse::<i32>().ok())),
+        CommandWord::Bot => Command::Bot,
     }
 }
 
-const HELP: &'static str = "\
+const HELP: &str = "\
 Commands:
   <dice>      input roll, e.g. 113666
   <row>       put roll on given row, e.g. D for Two Pairs
@@ -171,92 +192,315 @@ Commands:
   player N    switch current turn to player N
   bonus N     add N to score, counting towards bonus
   score N     add N to score without counting towards bonus
+  target N    maximize the chance of a final score of at least N,
+              or maximize expectation if N is 0
+  bot         let the bot roll its own dice and play this turn
+With a target, or with two players, the chance P of reaching the target
+or W of winning is maximized and shown next to the expected final score E.
+Playing to win assumes the other player maximizes their expected score.
+It needs the distribution table of the rules and is solved again after
+every move, which is only quick for the forced order variants such as
+yatzy-forced; with other rules, two players are advised by E alone.
 ";
 
+/// Remove `name` and the value after it from `args`, returning the value.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let i = args.iter().position(|a| a == name)?;
+    let value = args.get(i + 1).unwrap_or_else(|| panic!("{} needs a value", name)).clone();
+    args.drain(i..i + 2);
+    Some(value)
+}
+
+/// Writes down the rolls and keeps of the strategy it wraps.
+struct Narrator<'a> {
+    inner: &'a mut dyn Strategy,
+    log: String,
+    /// The roll last written down, unless the dice were rerolled since.
+    last_roll: Option<Outcome>,
+}
+
+impl<'a> Strategy for Narrator<'a> {
+    fn choose_keep(&mut self, state: State, outcome: Outcome, rerolls_left: usize) -> Outcome {
+        let keep = self.inner.choose_keep(state, outcome, rerolls_left);
+        self.log += &format!(" rolls {}", outcome);
+        self.last_roll = Some(outcome);
+        if keep != outcome {
+            self.log += &format!(", keeps {},", keep);
+            self.last_roll = None;
+        }
+        keep
+    }
+
+    fn choose_row(&mut self, state: State, outcome: Outcome, rerolls_left: usize) -> Action {
+        if self.last_roll != Some(outcome) {
+            self.log += &format!(" rolls {}", outcome);
+        }
+        self.inner.choose_row(state, outcome, rerolls_left)
+    }
+}
+
+/// Play a turn from `state` with `bot`, rolling its dice at random, and
+/// print the rolls and choices. Returns the encoding of the next state
+/// and the points, or None if the bot made a move that is not allowed.
+fn bot_turn(rules: &RuleSet, state: State, bot: &mut dyn Strategy) -> Option<(u32, u32)> {
+    let mut game = Game::with_players(rules.clone(), vec![Player { state, points: 0 }], 0);
+    let mut narrator = Narrator { inner: bot, log: String::from("The bot"), last_roll: None };
+    match play_turn(&mut game, &mut narrator, &mut rand::thread_rng()) {
+        Ok((action, points)) => {
+            println!("{} and chooses '{}' {} for {} points.", narrator.log, action.shorthand(rules), action.name(rules), points);
+            Some((game.players()[0].state.encode(rules), points))
+        },
+        Err(e) => {
+            println!("{}, but cannot move: {}", narrator.log, e);
+            None
+        },
+    }
+}
+
+type ObjectiveFn<'a> = Box<dyn FnMut(u32, u32) -> f64 + 'a>;
+type UtilityValue = Layer;
+
+/// Most states of rules to play to win with. The chance of winning is
+/// solved again from the current state whenever a player moves, which
+/// is only quick for rules as small as the forced order variants.
+const MAX_WIN_STATES: usize = 1 << 13;
+
+/// Utility values of the successors of `state` for beating `opponent`,
+/// who is assumed to maximize their expected score.
+fn compute_win_value(rules: &RuleSet, distribution: &DistributionStore, opponent: &Sheet, state: State) -> Option<UtilityValue> {
+    let opponent_distribution = distribution.distribution(opponent.state).ok()?;
+    let len = State::decode(rules, 0).upper_bound_points(rules) as usize + 1;
+    let utility = win_utility(opponent.points, &opponent_distribution, len);
+    Some(compute_successor_utility_value(rules, state, &utility, |_, _| ()))
+}
+
 fn main() {
-    let state_value = Store::new("state_value.bin").expect("Failed to read state value");
+    // The rules and --lazy, see `PlayOptions`. With --lazy, the
+    // expected scores are quick late in the game.
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let options = PlayOptions::take(&mut args);
+    let rules = options.rules.clone();
+    // With --strategy NAME, also show what that strategy would do.
+    let strategy_name = take_option(&mut args, "--strategy");
+    // The strategy of the bot, which plays turns on the bot command.
+    let bot_name = take_option(&mut args, "--bot").unwrap_or_else(|| "softmax".to_owned());
+    let value_objective = match args.first() {
+        Some(name) => Objective::by_name(name).expect("Unknown objective"),
+        None => Objective::Expectation,
+    };
+    let state_value = options.state_value(value_objective).expect("Failed to read state value");
+    let mut strategy = strategy_name.as_ref().map(|name| {
+        strategy_by_name(name, &rules, &*state_value, rand::random()).expect("Unknown strategy")
+    });
+    let mut bot = strategy_by_name(&bot_name, &rules, &*state_value, rand::random()).expect("Unknown bot strategy");
+    // Values of a risk-adjusted objective are not expected scores.
+    let base_label = if value_objective == Objective::Expectation { "E" } else { "V" };
+    let mut target_value = None;
+    let mut distribution = None;
+    // Utility values for beating the other player, for their state and
+    // points and the state of the current player.
+    let mut win_value: Option<((u32, u32, u32), UtilityValue)> = None;
+    // Keep advice for the objective in use, and for the table alone.
+    let mut valuation = Valuation::new();
+    let mut valuation_key = None;
+    let mut table_valuation = Valuation::new();
     let stdin = io::stdin();
     let mut reader = Tokenizer::new(stdin.lock());
 
-    let mut outcome_value = vec![0.0; max_outcome_encoding() + 1];
-    let mut reroll_value = vec![0.0; outcome_value.len()];
-
-    let mut game = Game {
-        players: vec![Player::new()],
+    let mut session = Session {
+        rules: rules.clone(),
+        players: vec![Sheet::new()],
         player_count: 1,
         player_index: 0,
         choices: Vec::new(),
         roll_index: 0,
         prompt: String::new(),
+        target: None,
     };
 
     loop {
-        let state = State::decode(game.players[game.player_index].state);
-        let points = game.players[game.player_index].points;
-        if game.roll_index == 0 {
-            let player_prompt = if game.player_count > 1 { format!("P{} ", game.player_index + 1) } else { String::new() };
-            game.prompt = format!("{}{:3} {} Input roll or command or 'help':", player_prompt, state.display_score(points), state);
+        let state = State::decode(&rules, session.players[session.player_index].state);
+        let points = session.players[session.player_index].points;
+        if session.roll_index == 0 {
+            let player_prompt = if session.player_count > 1 { format!("P{} ", session.player_index + 1) } else { String::new() };
+            let target_prompt = match session.target { Some(n) => format!("(target {}) ", n), None => String::new() };
+            session.prompt = format!("{}{:3} {} {}Input roll or command or 'help':", player_prompt, state.display_score(&rules, points), state.display(&rules), target_prompt);
         }
-        match parse_command(&mut reader, &mut game) {
+        match parse_command(&mut reader, &mut session) {
             Command::Roll(mut outcome) => {
-                if game.roll_index == 0 {
-                    compute_outcome_values(state, &mut |i| state_value.get(i), &mut outcome_value);
-                    compute_subset_expectations(&mut outcome_value);
-                    compute_reroll_value(&outcome_value, &mut reroll_value);
-                    compute_subset_expectations(&mut reroll_value);
-                    choose_reroll(&mut outcome, &reroll_value);
-                    game.prompt = format!("I would keep {}. Input roll:", outcome);
-                    game.roll_index += 1;
-                } else if game.roll_index == 1 {
-                    compute_outcome_values(state, &mut |i| state_value.get(i), &mut outcome_value);
-                    compute_subset_expectations(&mut outcome_value);
-                    choose_reroll(&mut outcome, &outcome_value);
-                    game.prompt = format!("I would keep {}. Input roll:", outcome);
-                    game.roll_index += 1;
-                    game.choices.clear();
-                } else if game.roll_index == 2 {
-                    game.choices.clear();
-                    actions(state, outcome, |action, next_state, action_points| {
-                        let i = next_state.encode();
-                        let value = state_value.get(i) + points as f64 + action_points as f64 - BONUS_LIMIT as f64;
-                        game.choices.push((value, action, i, action_points));
-                    });
-                    game.choices.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap());
-                    game.choices.reverse();
-                    for (i, (value, action, _state, points)) in game.choices.iter().enumerate() {
+                let rerolls = state.rerolls(&rules).saturating_sub(session.roll_index);
+                // Rows may be chosen after any roll; unused rerolls are saved.
+                let saved = (rerolls as u32).min(rules.max_saved_rerolls);
+                if session.player_count == 2 && session.target.is_none() {
+                    if let Some(d) = &distribution {
+                        let opponent = &session.players[1 - session.player_index];
+                        let key = (opponent.state, opponent.points, session.players[session.player_index].state);
+                        if win_value.as_ref().map(|w| w.0) != Some(key) {
+                            win_value = compute_win_value(&rules, d, opponent, state).map(|w| (key, w));
+                        }
+                    }
+                }
+                let mut expectation = |i, action_points| {
+                    state_value(i) + points as f64 + action_points as f64 - rules.upper_par() as f64
+                };
+                // The probability of reaching the target, the probability of
+                // winning against the other player, or the expected final score.
+                let (label, mut objective): (&str, ObjectiveFn) = match (session.target, &target_value, &win_value) {
+                    (Some(n), Some(t), _) => ("P", Box::new(TargetStore::objective(t, remaining_target(&rules, points, n)))),
+                    (None, _, Some((_, w))) if session.player_count == 2 => ("W", Box::new(utility_objective(w, points))),
+                    _ => (base_label, Box::new(&mut expectation)),
+                };
+                session.choices.clear();
+                actions(&rules, state, outcome, |action, next_state, action_points| {
+                    let i = next_state.with_saved_rerolls(saved).encode(&rules);
+                    session.choices.push((objective(i, action_points), action, i, action_points));
+                });
+                // Best first as in `Store::rank_actions`, NaN last.
+                session.choices.sort_by(|x, y| x.0.is_nan().cmp(&y.0.is_nan()).then(y.0.total_cmp(&x.0)));
+                // The objective changes with the points, target and opponent.
+                let key = (session.players[session.player_index].state, points, session.target, session.player_count, win_value.as_ref().map(|w| w.0));
+                if valuation_key != Some(key) {
+                    valuation.clear();
+                    table_valuation.clear();
+                    valuation_key = Some(key);
+                }
+                valuation.set_state_by(&rules, state, &mut objective);
+                drop(objective);
+                let roll = outcome;
+                outcome = valuation.best_keep(&rules, roll, rerolls);
+                let show = |value: f64| match label {
+                    "P" | "W" => format!("{}={:.1}%", label, 100.0 * value),
+                    _ => format!("{}={:.1}", label, value),
+                };
+                let show_loss = |loss: f64| match label {
+                    "P" | "W" => format!("{:.1}%", -100.0 * loss),
+                    _ => format!("{:.1}", -loss),
+                };
+                // Next to a chance, the expected final score of the same choice.
+                let show_expectation = |e: f64| if label == base_label { String::new() } else { format!(", {}={:.1}", base_label, e) };
+                if rerolls > 0 {
+                    table_valuation.set_state_by(&rules, state, &mut expectation);
+                    let keeps = valuation.rank_keeps(&rules, roll, rerolls);
+                    let table_keeps = table_valuation.rank_keeps(&rules, roll, rerolls);
+                    let keep_expectation = |keep: Outcome| table_keeps.iter().find(|k| k.0 == keep).map_or(f64::NAN, |k| k.1);
+                    println!("Keep options:");
+                    for &(keep, value) in keeps.iter() {
+                        let keep_name = if keep == roll {
+                            format!("keep {} and stop", keep)
+                        } else if keep.dice_count() == 0 {
+                            "reroll all".to_owned()
+                        } else {
+                            format!("keep {}", keep)
+                        };
+                        let e = show_expectation(keep_expectation(keep));
+                        if value == keeps[0].1 {
+                            println!("  {:20} ({}{})", keep_name, show(value), e);
+                        } else {
+                            println!("  {:20} ({}, {}{})", keep_name, show(value), show_loss(keeps[0].1 - value), e);
+                        }
+                    }
+                }
+                if let (Some(strategy), Some(name)) = (strategy.as_mut(), strategy_name.as_ref()) {
+                    let keep = if rerolls > 0 { strategy.choose_keep(state, roll, rerolls) } else { roll };
+                    if keep == roll {
+                        let action = strategy.choose_row(state, roll, rerolls);
+                        println!("The {} strategy would choose '{}' {}.", name, action.shorthand(&rules), action.name(&rules));
+                    } else {
+                        println!("The {} strategy would keep {}.", name, keep);
+                    }
+                }
+                if rerolls > 0 && outcome != roll {
+                    let table_keep = table_valuation.best_keep(&rules, roll, rerolls);
+                    if table_keep != outcome {
+                        println!("To maximize {} I would keep {} instead.", value_objective.name(), table_keep);
+                    }
+                    let hits = valuation.hit_probabilities(&rules, outcome, rerolls - 1);
+                    let mut chances = Vec::new();
+                    for (&comb, &p) in rules.combs().iter().zip(hits.combs.iter()) {
+                        if !state.has_comb(comb) && p > 0.0 {
+                            chances.push(format!("{} {:.0}%", rules.comb_symbol(comb), 100.0 * p));
+                        }
+                    }
+                    for (d, &p) in hits.sides.iter().enumerate() {
+                        if !state.has_side(d) && p > 0.0 {
+                            chances.push(format!("{} {:.0}%", d + 1, 100.0 * p));
+                        }
+                    }
+                    println!("Chance to score in each open row: {}", chances.join(", "));
+                    session.prompt = format!("I would keep {}. Input roll:", outcome);
+                    session.roll_index += 1;
+                } else {
+                    for (i, &(value, ref action, next_state, points)) in session.choices.iter().enumerate() {
+                        let e = show_expectation(expectation(next_state, points));
                         if i == 0 {
-                            println!("I would choose '{}' {} for {} points (E={:.1}). All possibilities:", action.shorthand(), action.name(), points, value);
+                            println!("I would choose '{}' {} for {} points ({}{}). All possibilities:", action.shorthand(&rules), action.name(&rules), points, show(value), e);
                         }
-                        println!("  {}  {:25} {:3} pts (E={:.1})", action.shorthand(), action.name(), points, value);
+                        println!("  {}  {:25} {:3} pts ({}{})", action.shorthand(&rules), action.name(&rules), points, show(value), e);
                     }
-                    game.prompt = "Which action do you choose?".to_owned();
+                    session.prompt = "Which action do you choose?".to_owned();
                 }
             },
             Command::Players(n) => {
-                game.player_count = n;
-                while game.players.len() < game.player_count {
-                    game.players.push(Player::new());
+                session.player_count = n;
+                while session.players.len() < session.player_count {
+                    session.players.push(Sheet::new());
                 }
-                game.choices.clear();
+                if n == 2 && distribution.is_none() && rules.state_count() > MAX_WIN_STATES {
+                    println!("Playing to win needs at most {} states, but {} has {}; maximizing expected score instead. \
+                              Try the forced order variant {}-forced.", MAX_WIN_STATES, rules.name, rules.state_count(), rules.name);
+                } else if n == 2 && distribution.is_none() {
+                    match DistributionStore::new(&rules.distribution_path()) {
+                        Ok(d) => distribution = Some(d),
+                        Err(e) => println!("Could not read {}: {}", rules.distribution_path(), e),
+                    }
+                }
+                session.choices.clear();
             },
             Command::Player(i) => {
-                if i >= 1 && i <= game.player_count {
-                    game.player_index = i - 1;
+                if i >= 1 && i <= session.player_count {
+                    session.player_index = i - 1;
                 }
-                game.choices.clear();
+                session.choices.clear();
             },
             Command::Action(s, p) => {
-                game.players[game.player_index].state = s;
-                game.players[game.player_index].points += p;
-                game.player_index = (game.player_index + 1) % game.player_count;
-                game.choices.clear();
-                game.roll_index = 0;
+                session.players[session.player_index].state = s;
+                session.players[session.player_index].points += p;
+                session.player_index = (session.player_index + 1) % session.player_count;
+                session.choices.clear();
+                session.roll_index = 0;
+            },
+            Command::Target(n) => {
+                if n <= 0 {
+                    session.target = None;
+                } else {
+                    if target_value.is_none() {
+                        match TargetStore::new(&rules.target_path()) {
+                            Ok(t) => target_value = Some(t),
+                            Err(e) => println!("Could not read {}: {}", rules.target_path(), e),
+                        }
+                    }
+                    if target_value.is_some() {
+                        session.target = Some(n);
+                    }
+                }
+                session.choices.clear();
+            },
+            Command::Bot => {
+                let (s, p) = match bot_turn(&rules, state, &mut *bot) {
+                    Some(r) => r,
+                    None => continue,
+                };
+                session.players[session.player_index].state = s;
+                session.players[session.player_index].points += p;
+                session.player_index = (session.player_index + 1) % session.player_count;
+                session.choices.clear();
+                session.roll_index = 0;
             },
             Command::Help => {
                 println!("{}", HELP);
             },
             Command::Score(n) => {
-                let p = &mut game.players[game.player_index].points;
+                let p = &mut session.players[session.player_index].points;
                 if n < 0 {
                     *p = p.saturating_sub((-n) as u32);
                 } else {
@@ -264,16 +508,16 @@ fn main() {
                 }
             },
             Command::Bonus(n) => {
-                let p = &mut game.players[game.player_index];
-                let mut s = State::decode(p.state);
+                let p = &mut session.players[session.player_index];
+                let mut s = State::decode(&rules, p.state);
                 if n < 0 {
                     p.points = p.points.saturating_sub((-n) as u32);
                     s.score = s.score.saturating_sub((-n) as u32);
                 } else {
                     p.points = p.points.saturating_add(n as u32);
-                    s.score = s.score.saturating_add(n as u32).min(BONUS_LIMIT);
+                    s.score = s.score.saturating_add(n as u32).min(rules.bonus_limit);
                 }
-                p.state = s.encode();
+                p.state = s.encode(&rules);
             },
         }
     }
@@ -1,56 +1,49 @@
+//! Play games with the best strategy and report statistics of the final
+//! scores: mean, standard deviation, percentiles, a histogram, how often
+//! the bonus is reached, and the average points and scratch frequency of
+//! each row. The mean is checked against the expected score of the table.
+//!
+//! Usage: self_play [--games N] [--seed S] [--threads N] [--strategy NAME] [--verbose] [--lazy] [RULES],
+//! where RULES is the name of a rule set and defaults to "super-yahtzee".
+//! Plays 10000 games on all available cores by default. Game i is played
+//! with random numbers seeded by S + i, so the results only depend on the
+//! seed and not on the number of threads. With --verbose, the rolls and
+//! choices of every game are printed. With --strategy, the games are
+//! played by "greedy", "random", "heuristic" or "softmax" instead of
+//! "optimal", see `strategy_by_name`, rolling dice by the same random
+//! numbers. E.g. --strategy softmax:2:0.5 plays with temperature 2 and a
+//! bias of 0.5 toward the rows scoring the most now. See `PlayOptions`
+//! for RULES and --lazy.
 extern crate rand;
-extern crate byteorder;
 
-use std::{io, fs};
-use byteorder::{LittleEndian, ReadBytesExt};
+use std::{env, process, thread};
+use std::sync::Mutex;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 
 extern crate yahtzeevalue;
 use yahtzeevalue::*;
 use yahtzeevalue::constants::*;
 
-fn random_outcome<R: rand::Rng>(rng: &mut R) -> Outcome {
-    let mut outcome = Outcome::empty();
-    for _ in 0..DICE_COUNT {
-        let roll = rng.gen_range(0, SIDES);
-        outcome.histogram[roll] += 1;
-    }
-    outcome
-}
-
-fn random_reroll<R: rand::Rng>(rng: &mut R, outcome: &mut Outcome) {
-    let mut c = 0;
-    for i in 0..SIDES {
-        c += outcome.histogram[i];
-    }
-    for _ in (c as usize)..DICE_COUNT {
-        let roll = rng.gen_range(0, SIDES);
-        outcome.histogram[roll] += 1;
-    }
-}
-
-fn read_state_value() -> io::Result<Vec<f64>> {
-    let file = fs::File::open("state_value.bin")?;
-    let size = file.metadata()?.len() as usize;
-    let mut reader = io::BufReader::new(file);
-    let mut state_value = vec![0f64; size / 8];
-    for x in state_value.iter_mut() {
-        *x = reader.read_f64::<LittleEndian>()?;
-    }
-    Ok(state_value)
-}
+/// Percentiles to report.
+const PERCENTILES: [u32; 9] = [1, 5, 10, 25, 50, 75, 90, 95, 99];
+/// Points per bar of the histogram.
+const HISTOGRAM_WIDTH: i64 = 10;
+/// Half width of the 99.9% confidence interval in standard errors.
+const CONFIDENCE_Z: f64 = 3.29;
 
-fn test_state_value(state_value: &Vec<f64>) {
+fn test_state_value(rules: &RuleSet, state_value: &StateValueFn) {
     let s1 = State::initial().with_side(0).with_score(5);
     let s2 = State::initial().with_comb(S33);
-    let e1 = state_value[s1.encode() as usize] + 5.0 - BONUS_LIMIT as f64;
-    let e2 = state_value[s2.encode() as usize] - BONUS_LIMIT as f64;
+    let e1 = state_value(s1.encode(rules)) + 5.0 - rules.upper_par() as f64;
+    let e2 = state_value(s2.encode(rules)) - rules.upper_par() as f64;
     println!("For 5 1's, scratching 2x3 has expectation {}, taking the 1's has expectation {}", e2, e1);
     if e2 > e1 {
         for d in 0..SIDES {
-            for c in 0..DICE_COUNT + 1 {
+            for c in 0..rules.dice_count + 1 {
                 let score = c as u32 * (1 + d) as u32;
                 let s = State::initial().with_side(d).with_score(score);
-                let e = state_value[s.encode() as usize] + score as f64 - BONUS_LIMIT as f64;
+                let e = state_value(s.encode(rules)) + score as f64 - rules.upper_par() as f64;
                 println!("Keeping {} {}'s => {}", c, d + 1, e);
             }
         }
@@ -58,57 +51,158 @@ fn test_state_value(state_value: &Vec<f64>) {
     }
 }
 
-fn main() {
-    let state_value = read_state_value().expect("Failed to read state value");
-    test_state_value(&state_value);
-    println!("Expected score: {}", state_value[0] - BONUS_LIMIT as f64);
-    let mut rng = rand::thread_rng();
-    let mut outcome_value = vec![0.0; max_outcome_encoding() + 1];
-    let mut reroll_value = vec![0.0; outcome_value.len()];
-    loop {
-        let mut points = 0;
-        let mut state = State {
-            combination_mask: 0,
-            sides_mask: 0,
-            score: 0,
+/// What happened in a game.
+struct GameResult {
+    score: i64,
+    bonus: bool,
+    /// Points of each row by `Action::index`, not counting the bonus.
+    row_points: Vec<u32>,
+}
+
+fn play_game(rules: &RuleSet, state_value: &StateValueFn, strategy: &mut dyn Strategy, rng: &mut StdRng, verbose: bool) -> GameResult {
+    let mut game = Game::new(rules.clone(), 1);
+    let mut row_points = vec![0; rules.comb_count() + SIDES];
+    let mut log = String::new();
+    while !game.done() {
+        let state = game.state();
+        let points = game.player().points;
+        let mut outcome = game.roll_random(rng).expect("Cannot roll");
+        log += &format!("{:3} {} Roll {}", state.display_score(rules, points), state.display(rules), outcome);
+        while game.rerolls() > 0 {
+            let keep = strategy.choose_keep(state, outcome, game.rerolls());
+            if keep == outcome {
+                break;
+            }
+            game.keep(keep).expect("Cannot keep");
+            outcome = game.roll_random(rng).expect("Cannot roll");
+            log += &format!(", keep {:6} and reroll to {}", keep.to_string(), outcome);
+        }
+
+        let action = strategy.choose_row(state, outcome, game.rerolls());
+        let action_points = game.choose(action).expect("Cannot choose row");
+        let best = state_value(game.state().encode(rules)) + action_points as f64;
+        log += &format!(", {} => {:3} points (exp.: {:.4})\n", action.shorthand(rules), action_points, points as f64 + best - rules.upper_par() as f64);
+        row_points[action.index(rules)] = match action {
+            Action::Side(d) => (d as u32 + 1) * outcome.histogram[d] as u32,
+            Action::Combination(_) => action_points,
         };
-        while !state.done() {
-            let mut outcome = random_outcome(&mut rng);
-            print!("{:3} {} Roll {}", state.display_score(points), state, outcome);
-            compute_outcome_values(state, &mut |i| state_value[i as usize], &mut outcome_value);
-            compute_subset_expectations(&mut outcome_value);
-            compute_reroll_value(&outcome_value, &mut reroll_value);
-            compute_subset_expectations(&mut reroll_value);
-            choose_reroll(&mut outcome, &reroll_value);
-            print!(", keep {:6}", format!("{}", outcome));
-            random_reroll(&mut rng, &mut outcome);
-            print!(" and reroll to {}", outcome);
-            choose_reroll(&mut outcome, &outcome_value);
-            print!(", keep {:6}", format!("{}", outcome));
-            random_reroll(&mut rng, &mut outcome);
-            print!(" and reroll to {}", outcome);
+    }
+    if verbose {
+        println!("{}", log);
+    }
+    let player = game.player();
+    GameResult {
+        score: player.points as i64 - rules.upper_par() as i64,
+        bonus: rules.bonus > 0 && player.state.score >= rules.bonus_limit,
+        row_points,
+    }
+}
 
-            let mut best = -1f64;
-            let mut best_action = None;
-            let mut best_state = None;
-            let mut best_points = 0;
-            let mut action_count = 0;
-            actions(state, outcome, |action, next_state, points| {
-                action_count += 1;
-                let i = next_state.encode() as usize;
-                let value = state_value[i] + points as f64;
-                if value > best {
-                    best = value;
-                    best_action = Some(action);
-                    best_state = Some(next_state);
-                    best_points = points;
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let options = PlayOptions::take(&mut args);
+    let rules = options.rules.clone();
+    let mut strategy_name = "optimal".to_owned();
+    let mut verbose = false;
+    let mut games = 10000;
+    let mut seed = None;
+    let mut threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--verbose" {
+            verbose = true;
+        } else if arg == "--strategy" {
+            strategy_name = args.next().expect("--strategy needs a name");
+        } else if arg == "--games" {
+            games = args.next().and_then(|n| n.parse().ok()).expect("--games needs a number");
+        } else if arg == "--seed" {
+            seed = Some(args.next().and_then(|n| n.parse().ok()).expect("--seed needs a number"));
+        } else if arg == "--threads" {
+            threads = args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0).expect("--threads needs a positive number");
+        } else {
+            panic!("Unknown rule set or option {}", arg);
+        }
+    }
+    let seed: u64 = seed.unwrap_or_else(rand::random);
+    let state_value = options.state_value(Objective::Expectation).expect("Failed to read state value");
+    if !options.lazy && rules.name == "super-yahtzee" {
+        test_state_value(&rules, &*state_value);
+    }
+    let expected = state_value(0) - rules.upper_par() as f64;
+    if strategy_by_name(&strategy_name, &rules, &*state_value, 0).is_none() {
+        eprintln!("Unknown strategy {}", strategy_name);
+        process::exit(2);
+    }
+    println!("Playing {} games of {} with the {} strategy, seed {} on {} threads", games, rules.name, strategy_name, seed, threads);
+
+    let results: Mutex<Vec<Option<GameResult>>> = Mutex::new((0..games).map(|_| None).collect());
+    thread::scope(|scope| {
+        for t in 0..threads {
+            let (rules, state_value, results, strategy_name) = (&rules, &*state_value, &results, &strategy_name[..]);
+            scope.spawn(move || {
+                for i in (t..games).step_by(threads) {
+                    let game_seed = seed.wrapping_add(i as u64);
+                    let mut rng = StdRng::seed_from_u64(game_seed);
+                    // Its own random numbers, so the dice are the same for every strategy.
+                    let mut strategy = strategy_by_name(strategy_name, rules, state_value, !game_seed).expect("Unknown strategy");
+                    let result = play_game(rules, state_value, &mut *strategy, &mut rng, verbose);
+                    results.lock().unwrap()[i] = Some(result);
                 }
             });
-            assert!(action_count > 0);
-            println!(", {} => {:3} points (exp.: {:.4})", best_action.unwrap().shorthand(), best_points, points as f64 + best - BONUS_LIMIT as f64);
-            state = best_state.unwrap();
-            points += best_points;
         }
-        println!("\n");
+    });
+    let results: Vec<GameResult> = results.into_inner().unwrap().into_iter().map(|r| r.unwrap()).collect();
+    if results.is_empty() {
+        return;
+    }
+
+    let n = results.len() as f64;
+    let mut scores: Vec<i64> = results.iter().map(|r| r.score).collect();
+    scores.sort_unstable();
+    let mean = scores.iter().sum::<i64>() as f64 / n;
+    let variance = scores.iter().map(|&s| (s as f64 - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+    let sd = variance.sqrt();
+    println!("Mean score: {:.3}, standard deviation: {:.3}, min: {}, max: {}", mean, sd, scores[0], scores[scores.len() - 1]);
+    let percentiles: Vec<String> = PERCENTILES.iter().map(|&p| {
+        // Nearest rank
+        let rank = ((p as f64 / 100.0 * n).ceil() as usize).clamp(1, scores.len());
+        format!("{}%: {}", p, scores[rank - 1])
+    }).collect();
+    println!("Percentiles: {}", percentiles.join(", "));
+    if rules.bonus > 0 {
+        let bonus = results.iter().filter(|r| r.bonus).count();
+        println!("Bonus reached in {:.2}% of games", 100.0 * bonus as f64 / n);
+    }
+
+    println!("Score histogram:");
+    let bucket = |s: i64| s.div_euclid(HISTOGRAM_WIDTH);
+    let first = bucket(scores[0]);
+    let mut counts = vec![0usize; (bucket(scores[scores.len() - 1]) - first + 1) as usize];
+    for &s in scores.iter() {
+        counts[(bucket(s) - first) as usize] += 1;
+    }
+    let most = *counts.iter().max().unwrap();
+    for (b, &count) in counts.iter().enumerate() {
+        let low = (first + b as i64) * HISTOGRAM_WIDTH;
+        let bar = "#".repeat((50 * count).div_ceil(most));
+        println!("  {:4}-{:<4} {:6} {}", low, low + HISTOGRAM_WIDTH - 1, count, bar);
+    }
+
+    let rows: Vec<Action> = rules.combs().iter().map(|&c| Action::Combination(c)).chain((0..SIDES).map(Action::Side)).collect();
+    let width = rows.iter().map(|a| a.name(&rules).len()).max().unwrap_or(0);
+    println!("  {:w$} Average Scratched", "Row", w = width + 2);
+    for action in rows {
+        let i = action.index(&rules);
+        let total: u64 = results.iter().map(|r| r.row_points[i] as u64).sum();
+        let scratched = results.iter().filter(|r| r.row_points[i] == 0).count();
+        println!("  {} {:w$} {:7.3} {:8.2}%", action.shorthand(&rules), action.name(&rules), total as f64 / n, 100.0 * scratched as f64 / n, w = width);
+    }
+
+    let margin = CONFIDENCE_Z * sd / n.sqrt();
+    println!("Expected score from the table: {:.3}, 99.9% confidence interval of the mean: [{:.3}, {:.3}]", expected, mean - margin, mean + margin);
+    // Only the optimal strategy is expected to reach the table's score.
+    if strategy_name == "optimal" && (expected - mean).abs() > margin {
+        println!("The expected score is outside the confidence interval");
+        process::exit(1);
     }
 }
@@ -0,0 +1,209 @@
+//! Play matches between strategies and report how often each wins, by
+//! how much, and how each fares against each of the others.
+//!
+//! Usage: tournament [--games N] [--seed S] [--threads N] [--lazy] [RULES] STRATEGY STRATEGY...,
+//! where RULES is the name of a rule set and defaults to "super-yahtzee",
+//! and each STRATEGY is a name for `strategy_by_name`, e.g.
+//! `tournament yatzy optimal greedy heuristic`. Every strategy plays in
+//! each of N matches, 1000 by default.
+//!
+//! The players of a match roll the same dice: each player rolls with its
+//! own random numbers seeded by S + i for match i. Players that keep the
+//! same dice thus get the same rolls, so differences in score come from
+//! the choices and not from luck, and fewer matches are needed to tell
+//! the strategies apart. Ties count as a fraction of a win for each of
+//! the tied players. See `PlayOptions` for RULES and --lazy.
+extern crate rand;
+
+use std::{env, process, thread};
+use std::sync::Mutex;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+extern crate yahtzeevalue;
+use yahtzeevalue::*;
+
+/// Half width of the 95% confidence interval in standard errors.
+const CONFIDENCE_Z: f64 = 1.96;
+
+/// Final scores of the players of a match, not counting the upper par.
+fn play_match(rules: &RuleSet, state_value: &StateValueFn, names: &[String], seed: u64) -> Vec<i64> {
+    let mut strategies: Vec<Box<dyn Strategy + '_>> = names.iter().enumerate().map(|(p, name)| {
+        // Its own random numbers, so the dice do not depend on the strategy.
+        strategy_by_name(name, rules, state_value, !seed ^ p as u64).expect("Unknown strategy")
+    }).collect();
+    let mut rngs: Vec<StdRng> = names.iter().map(|_| StdRng::seed_from_u64(seed)).collect();
+    let mut game = Game::new(rules.clone(), names.len());
+    while !game.done() {
+        let p = game.player_index();
+        play_turn(&mut game, &mut *strategies[p], &mut rngs[p]).expect("Illegal move");
+    }
+    game.players().iter().map(|p| p.points as i64 - rules.upper_par() as i64).collect()
+}
+
+/// The state values for the strategies `names`, which must be known to
+/// `strategy_by_name`. The table is only read if one of them uses it.
+fn state_value(options: &PlayOptions, names: &[String]) -> Box<StateValueFn> {
+    if options.lazy || names.iter().any(|n| strategy_uses_state_value(n) == Some(true)) {
+        options.state_value(Objective::Expectation).expect("Failed to read state value")
+    } else {
+        Box::new(|_| f64::NAN)
+    }
+}
+
+/// Mean and half width of the confidence interval of the mean.
+fn mean_error(xs: &[f64]) -> (f64, f64) {
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    let variance = xs.iter().map(|&x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+    (mean, CONFIDENCE_Z * (variance / n).sqrt())
+}
+
+/// Share of the win of `score` among `scores`, 1/k for a k-way tie.
+fn win_share(score: i64, scores: &[i64]) -> f64 {
+    if scores.iter().any(|&s| s > score) {
+        0.0
+    } else {
+        1.0 / scores.iter().filter(|&&s| s == score).count() as f64
+    }
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let options = PlayOptions::take(&mut args);
+    let rules = options.rules.clone();
+    let mut names = Vec::new();
+    let mut games = 1000;
+    let mut seed = None;
+    let mut threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--games" {
+            games = args.next().and_then(|n| n.parse().ok()).expect("--games needs a number");
+        } else if arg == "--seed" {
+            seed = Some(args.next().and_then(|n| n.parse().ok()).expect("--seed needs a number"));
+        } else if arg == "--threads" {
+            threads = args.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0).expect("--threads needs a positive number");
+        } else {
+            names.push(arg);
+        }
+    }
+    if names.len() < 2 {
+        eprintln!("Usage: tournament [--games N] [--seed S] [--threads N] [--lazy] [RULES] STRATEGY STRATEGY...");
+        process::exit(2);
+    }
+    let seed: u64 = seed.unwrap_or_else(rand::random);
+    if let Some(name) = names.iter().find(|n| strategy_uses_state_value(n).is_none()) {
+        eprintln!("Unknown strategy {}", name);
+        process::exit(2);
+    }
+    let state_value = state_value(&options, &names);
+    println!("Playing {} matches of {} between {}, seed {} on {} threads", games, rules.name, names.join(", "), seed, threads);
+
+    let results: Mutex<Vec<Option<Vec<i64>>>> = Mutex::new((0..games).map(|_| None).collect());
+    thread::scope(|scope| {
+        for t in 0..threads {
+            let (rules, state_value, results, names) = (&rules, &*state_value, &results, &names[..]);
+            scope.spawn(move || {
+                for i in (t..games).step_by(threads) {
+                    let scores = play_match(rules, state_value, names, seed.wrapping_add(i as u64));
+                    results.lock().unwrap()[i] = Some(scores);
+                }
+            });
+        }
+    });
+    let results: Vec<Vec<i64>> = results.into_inner().unwrap().into_iter().map(|r| r.unwrap()).collect();
+    if results.is_empty() {
+        return;
+    }
+
+    let width = names.iter().map(|n| n.len()).max().unwrap().max(8);
+    println!("  {:w$} {:>8} {:>24} {:>16}", "Strategy", "Mean", "Win rate (95% CI)", "Margin", w = width);
+    for (p, name) in names.iter().enumerate() {
+        let scores: Vec<f64> = results.iter().map(|r| r[p] as f64).collect();
+        let wins: Vec<f64> = results.iter().map(|r| win_share(r[p], r)).collect();
+        // Against the best of the other players.
+        let margins: Vec<f64> = results.iter().map(|r| {
+            let best = r.iter().enumerate().filter(|&(q, _)| q != p).map(|(_, &s)| s).max().unwrap();
+            (r[p] - best) as f64
+        }).collect();
+        let (mean, _) = mean_error(&scores);
+        let (win, win_error) = mean_error(&wins);
+        let (margin, margin_error) = mean_error(&margins);
+        println!("  {:w$} {:8.2} {:>24} {:>16}", name, mean,
+                 format!("{:.2}% [{:.2}%, {:.2}%]", 100.0 * win, 100.0 * (win - win_error).max(0.0), 100.0 * (win + win_error).min(1.0)),
+                 format!("{:+.2} ± {:.2}", margin, margin_error), w = width);
+    }
+    println!("The margin is the score minus the best score of the other players.");
+
+    let pair = |p: usize, q: usize| {
+        let wins: Vec<f64> = results.iter().map(|r| win_share(r[p], &[r[p], r[q]])).collect();
+        let margins: Vec<f64> = results.iter().map(|r| (r[p] - r[q]) as f64).collect();
+        (mean_error(&wins), mean_error(&margins))
+    };
+    let column = width.max(16);
+    let header: String = names.iter().map(|n| format!(" {:>c$}", n, c = column)).collect();
+    println!("Head-to-head win rates of the row against the column (95% CI):");
+    println!("  {:w$}{}", "", header, w = width);
+    for (p, name) in names.iter().enumerate() {
+        let cells: String = (0..names.len()).map(|q| {
+            let cell = if p == q {
+                "-".to_owned()
+            } else {
+                let ((win, error), _) = pair(p, q);
+                format!("{:.2}% ± {:.2}%", 100.0 * win, 100.0 * error)
+            };
+            format!(" {:>c$}", cell, c = column)
+        }).collect();
+        println!("  {:w$}{}", name, cells, w = width);
+    }
+    println!("Head-to-head margins of the row over the column (95% CI):");
+    println!("  {:w$}{}", "", header, w = width);
+    for (p, name) in names.iter().enumerate() {
+        let cells: String = (0..names.len()).map(|q| {
+            let cell = if p == q {
+                "-".to_owned()
+            } else {
+                let (_, (margin, error)) = pair(p, q);
+                format!("{:+.2} ± {:.2}", margin, error)
+            };
+            format!(" {:>c$}", cell, c = column)
+        }).collect();
+        println!("  {:w$}{}", name, cells, w = width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_rules() -> RuleSet {
+        RuleSet::new("small", 3, 2, 1, 10, &[constants::S2, constants::CHANCE])
+    }
+
+    #[test]
+    fn softmax_player() {
+        let options = PlayOptions { rules: small_rules(), lazy: true };
+        let names = vec!["softmax".to_owned(), "greedy".to_owned()];
+        let state_value = state_value(&options, &names);
+        for seed in 0..10 {
+            let scores = play_match(&options.rules, &*state_value, &names, seed);
+            assert_eq!(scores.len(), 2);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Failed to read state value")]
+    fn softmax_reads_the_table() {
+        let options = PlayOptions { rules: small_rules(), lazy: false };
+        let _ = state_value(&options, &["greedy".to_owned(), "softmax:2".to_owned()]);
+    }
+
+    #[test]
+    fn no_table_without_its_strategies() {
+        // There is no table file of these rules to read.
+        let options = PlayOptions { rules: small_rules(), lazy: false };
+        let names = vec!["greedy".to_owned(), "random".to_owned(), "heuristic".to_owned()];
+        assert!(state_value(&options, &names)(0).is_nan());
+    }
+}
@@ -0,0 +1,121 @@
+//! Compare the decisions made with a table written by convert_table to
+//! those made with the full precision table, and report the worst-case
+//! loss of expected score caused by the lower precision.
+//!
+//! Usage: verify_table ENCODING [RULES] [--step N], where every N'th
+//! state is checked (default 1). Checking every state takes about as long
+//! as computing the table.
+extern crate yahtzeevalue;
+
+use std::env;
+
+use yahtzeevalue::*;
+
+/// Decisions that are worse with the converted table, and the largest loss.
+/// Choosing differently between options of equal value is no change.
+#[derive(Default)]
+struct Changes {
+    decisions: usize,
+    changed: usize,
+    worst_loss: f64,
+    worst: String,
+}
+
+impl Changes {
+    fn add(&mut self, loss: Option<f64>, describe: impl FnOnce() -> String) {
+        self.decisions += 1;
+        if let Some(loss) = loss.filter(|&l| l > 0.0) {
+            self.changed += 1;
+            if loss > self.worst_loss || self.changed == 1 {
+                self.worst_loss = loss;
+                self.worst = describe();
+            }
+        }
+    }
+
+    fn report(&self, what: &str) {
+        println!("{}: {} of {} decisions changed", what, self.changed, self.decisions);
+        if self.changed > 0 {
+            println!("  worst loss {:.3e} points: {}", self.worst_loss, self.worst);
+        }
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let encoding = args.next().and_then(|n| Encoding::by_name(&n)).expect("Usage: verify_table f32|u16 [RULES] [--step N]");
+    let mut rules = RuleSet::super_yahtzee();
+    let mut step = 1;
+    while let Some(arg) = args.next() {
+        if arg == "--step" {
+            step = args.next().and_then(|n| n.parse().ok()).expect("--step needs a number");
+        } else {
+            rules = RuleSet::by_name(&arg).expect("Unknown rule set");
+        }
+    }
+    let reference = Store::with_rules(&rules.table_path(), rules.clone()).expect("Failed to read state value");
+    let compact = Store::with_rules(&encoding.table_path(&rules), rules.clone()).expect("Failed to read converted table");
+    assert_eq!(compact.encoding(), encoding, "Converted table has the wrong size");
+
+    let mut worst_error = 0.0;
+    let mut worst_error_state = 0;
+    for i in 0..reference.len() {
+        if let (Ok(a), Ok(b)) = (reference.get(i), compact.get(i)) {
+            if (a - b).abs() > worst_error {
+                worst_error = (a - b).abs();
+                worst_error_state = i;
+            }
+        }
+    }
+    println!("Largest value error {:.6} in state {}", worst_error, State::decode(&rules, worst_error_state).display(&rules));
+
+    let mut reference_value = |i| reference.get(i).unwrap_or(f64::NAN);
+    let mut compact_value = |i| compact.get(i).unwrap_or(f64::NAN);
+    let mut exact = Valuation::new();
+    let mut approximate = Valuation::new();
+    let mut rows = Changes::default();
+    let mut keeps = Changes::default();
+    let states = rules.state_count();
+    for i in (0..states as u32).step_by(step) {
+        let state = State::decode(&rules, i);
+        if state.done(&rules) || reference.get(i).is_err() {
+            continue;
+        }
+        exact.set_state(&rules, state, &mut reference_value);
+        approximate.set_state(&rules, state, &mut compact_value);
+        for rerolls in 0..(state.rerolls(&rules) + 1) {
+            // Rows only depend on the rerolls saved by stopping.
+            let check_rows = rerolls == 0 || rerolls as u32 <= rules.max_saved_rerolls;
+            for o in outcomes(&rules) {
+                let describe = || format!("{} roll {} with {} rerolls left", state.display(&rules), o, rerolls);
+                if check_rows {
+                    let (best, best_state, best_points) = exact.best_row(&rules, o, rerolls).unwrap();
+                    let (chosen, chosen_state, chosen_points) = approximate.best_row(&rules, o, rerolls).unwrap();
+                    let loss = if chosen == best {
+                        None
+                    } else {
+                        Some(reference_value(best_state) + best_points as f64 - reference_value(chosen_state) - chosen_points as f64)
+                    };
+                    rows.add(loss, describe);
+                }
+                if rerolls > 0 {
+                    let best = exact.best_keep(&rules, o, rerolls);
+                    let chosen = approximate.best_keep(&rules, o, rerolls);
+                    let loss = if chosen == best {
+                        None
+                    } else {
+                        let ranked = exact.rank_keeps(&rules, o, rerolls);
+                        let chosen_value = ranked.iter().find(|k| k.0 == chosen).unwrap().1;
+                        Some(ranked[0].1 - chosen_value)
+                    };
+                    keeps.add(loss, describe);
+                }
+            }
+        }
+        if (i as usize / step).is_multiple_of(1 << 12) {
+            eprintln!("{:8}/{}", i, states);
+        }
+    }
+    rows.report("Rows");
+    keeps.report("Keeps");
+}
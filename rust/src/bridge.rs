@@ -1,4 +1,5 @@
 use std::{panic, mem, fmt};
+use std::sync::Mutex;
 use std::os::raw::{c_uint, c_char};
 
 pub trait CError: fmt::Display {
@@ -9,9 +10,9 @@ struct PanicError();
 
 impl fmt::Display for PanicError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match unsafe {&PANIC_INFO} {
-            &Some(ref s) => write!(f, "{}", s),
-            &None => write!(f, "no panic info"),
+        match *PANIC_INFO.lock().unwrap() {
+            Some(ref s) => write!(f, "{}", s),
+            None => write!(f, "no panic info"),
         }
     }
 }
@@ -27,21 +28,19 @@ pub struct NativeError {
     code: c_uint,
 }
 
-static mut PANIC_INFO: Option<String> = None;
+static PANIC_INFO: Mutex<Option<String>> = Mutex::new(None);
 
 // From https://youtu.be/zmtHaZG7pPc?t=21m29s
-fn silent_panic_handler(pi: &panic::PanicInfo) {
+fn silent_panic_handler(pi: &panic::PanicHookInfo) {
     let pl = pi.payload();
     let payload = if let Some(s) = pl.downcast_ref::<&str>() { s }
-    else if let Some(s) = pl.downcast_ref::<String>() { &s }
+    else if let Some(s) = pl.downcast_ref::<String>() { s }
     else { "?" };
     let position = if let Some(p) = pi.location() {
         format!("At {}:{}: ", p.file(), p.line())
     }
     else { "".to_owned() };
-    unsafe {
-        PANIC_INFO = Some(format!("{}{}", position, payload));
-    }
+    *PANIC_INFO.lock().unwrap() = Some(format!("{}{}", position, payload));
 }
 // End from
 
@@ -50,7 +49,7 @@ pub fn set_panic_hook() {
 }
 
 // From https://youtu.be/zmtHaZG7pPc?t=21m39s
-unsafe fn set_err(err: &CError, err_out: *mut NativeError) {
+unsafe fn set_err(err: &dyn CError, err_out: *mut NativeError) {
     if err_out.is_null() {
         return;
     }
@@ -74,7 +73,6 @@ pub unsafe fn landingpad<F: FnOnce() -> Result<T, E> + panic::UnwindSafe, T, E:
 }
 
 // From https://youtu.be/zmtHaZG7pPc?t=22m09s
-#[macro_use]
 macro_rules! export (
     ($n:ident($($an:ident: $aty:ty),*) -> Result<$rv:ty> $body:block) => (
         #[no_mangle]
@@ -1,15 +1,27 @@
 use std::os::raw::{c_int, c_char, c_double};
 use std::ffi::{CStr, CString};
-use crate::{Store, Result, ErrorKind, Outcome};
+use crate::{Store, Result, ErrorKind, Outcome, Game, RuleSet, Action, State};
 use crate::bridge::*;
 
+/// A row as returned by `yahtzeevalue_rank_actions`, see `RankedAction`.
+#[repr(C)]
+pub struct CRankedAction {
+    /// Score sheet position as returned by `yahtzeevalue_best_action`.
+    action: c_int,
+    points: c_int,
+    next_state: c_int,
+    /// points plus the table value of next_state, which for an
+    /// expectation table includes the upper section par of the rules.
+    total: c_double,
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn yahtzeevalue_init() {
     set_panic_hook();
 }
 
 export!(yahtzeevalue_free(buf: *mut c_char) -> Result<c_int> {
-    CString::from_raw(buf);
+    drop(CString::from_raw(buf));
     Ok(0)
 });
 
@@ -18,7 +30,12 @@ export!(yahtzeevalue_load(path: *const c_char) -> Result<*mut Store> {
 });
 
 export!(yahtzeevalue_unload(db: *mut Store) -> Result<c_int> {
-    Box::from_raw(db);
+    drop(Box::from_raw(db));
+    Ok(0)
+});
+
+export!(yahtzeevalue_verify(db: *mut Store) -> Result<c_int> {
+    (*db).verify()?;
     Ok(0)
 });
 
@@ -27,16 +44,28 @@ export!(yahtzeevalue_lookup(db: *mut Store, state: c_int) -> Result<c_double> {
     if state >= (*db).len() {
         return Err(ErrorKind::Range.into());
     }
-    Ok((*db).get(state))
+    (*db).get(state)
 });
 
-export!(yahtzeevalue_best_action(db: *mut Store, state: c_int, histogram: c_int) -> Result<c_int> {
+/// `rerolls` as taken by `Store::keep` and `Store::rank_actions`: from 0 to
+/// the rerolls of the state.
+fn check_rerolls(db: &Store, state: u32, rerolls: c_int) -> Result<usize> {
+    let rules = db.rules();
+    if rerolls < 0 || rerolls as usize > State::decode(rules, state).rerolls(rules) {
+        return Err(ErrorKind::Range.into());
+    }
+    Ok(rerolls as usize)
+}
+
+export!(yahtzeevalue_best_action(db: *mut Store, state: c_int, histogram: c_int, rerolls: c_int) -> Result<c_int> {
     let state = state as u32;
     if state >= (*db).len() {
         return Err(ErrorKind::Range.into());
     }
+    let rerolls = check_rerolls(&*db, state, rerolls)?;
+    (*db).get(state)?;
     let outcome = Outcome::decode(histogram as u32);
-    (*db).best_action(state, outcome).map(|v| v as c_int).ok_or(ErrorKind::GameOver.into())
+    (*db).best_action(state, outcome, rerolls).map(|v| v as c_int).ok_or(ErrorKind::GameOver.into())
 });
 
 export!(yahtzeevalue_keep_first(db: *mut Store, state: c_int, histogram: c_int) -> Result<c_int> {
@@ -44,6 +73,7 @@ export!(yahtzeevalue_keep_first(db: *mut Store, state: c_int, histogram: c_int)
     if state >= (*db).len() {
         return Err(ErrorKind::Range.into());
     }
+    (*db).get(state)?;
     let outcome = Outcome::decode(histogram as u32);
     Ok((*db).keep_first(state, outcome) as c_int)
 });
@@ -53,6 +83,93 @@ export!(yahtzeevalue_keep_second(db: *mut Store, state: c_int, histogram: c_int)
     if state >= (*db).len() {
         return Err(ErrorKind::Range.into());
     }
+    (*db).get(state)?;
     let outcome = Outcome::decode(histogram as u32);
     Ok((*db).keep_second(state, outcome) as c_int)
 });
+
+export!(yahtzeevalue_keep(db: *mut Store, state: c_int, histogram: c_int, rerolls: c_int) -> Result<c_int> {
+    let state = state as u32;
+    if state >= (*db).len() {
+        return Err(ErrorKind::Range.into());
+    }
+    let rerolls = check_rerolls(&*db, state, rerolls)?;
+    (*db).get(state)?;
+    let outcome = Outcome::decode(histogram as u32);
+    Ok((*db).keep(state, outcome, rerolls) as c_int)
+});
+
+// Returns the number of rows, best first, of which at most capacity are
+// written to out. out may be null if capacity is 0.
+export!(yahtzeevalue_rank_actions(db: *mut Store, state: c_int, histogram: c_int, rerolls: c_int,
+                                   out: *mut CRankedAction, capacity: c_int) -> Result<c_int> {
+    let state = state as u32;
+    if state >= (*db).len() || capacity < 0 || (out.is_null() && capacity > 0) {
+        return Err(ErrorKind::Range.into());
+    }
+    let rerolls = check_rerolls(&*db, state, rerolls)?;
+    (*db).get(state)?;
+    let outcome = Outcome::decode(histogram as u32);
+    let ranked = (*db).rank_actions(state, outcome, rerolls);
+    for (i, a) in ranked.iter().take(capacity as usize).enumerate() {
+        *out.add(i) = CRankedAction {
+            action: a.action.index((*db).rules()) as c_int,
+            points: a.points as c_int,
+            next_state: a.next_state.encode((*db).rules()) as c_int,
+            total: a.total,
+        };
+    }
+    Ok(ranked.len() as c_int)
+});
+
+export!(yahtzeevalue_game_new(rules: *const c_char, player_count: c_int) -> Result<*mut Game> {
+    let rules = RuleSet::by_name(CStr::from_ptr(rules).to_str()?).ok_or(ErrorKind::Range)?;
+    if player_count <= 0 {
+        return Err(ErrorKind::Range.into());
+    }
+    Ok(Box::into_raw(Box::new(Game::new(rules, player_count as usize))))
+});
+
+export!(yahtzeevalue_game_free(game: *mut Game) -> Result<c_int> {
+    drop(Box::from_raw(game));
+    Ok(0)
+});
+
+export!(yahtzeevalue_game_roll(game: *mut Game, histogram: c_int) -> Result<c_int> {
+    (*game).roll(Outcome::decode(histogram as u32))?;
+    Ok(0)
+});
+
+export!(yahtzeevalue_game_keep(game: *mut Game, histogram: c_int) -> Result<c_int> {
+    (*game).keep(Outcome::decode(histogram as u32))?;
+    Ok(0)
+});
+
+// action is a score sheet position as returned by yahtzeevalue_best_action.
+// Returns the points scored.
+export!(yahtzeevalue_game_choose(game: *mut Game, action: c_int) -> Result<c_int> {
+    let action = Action::from_index((*game).rules(), action as usize).ok_or(ErrorKind::IllegalRow)?;
+    Ok((*game).choose(action)? as c_int)
+});
+
+export!(yahtzeevalue_game_done(game: *mut Game) -> Result<c_int> {
+    Ok((*game).done() as c_int)
+});
+
+export!(yahtzeevalue_game_player(game: *mut Game) -> Result<c_int> {
+    Ok((*game).player_index() as c_int)
+});
+
+// The state of the player whose turn it is, for yahtzeevalue_lookup.
+export!(yahtzeevalue_game_state(game: *mut Game) -> Result<c_int> {
+    Ok((*game).state().encode((*game).rules()) as c_int)
+});
+
+export!(yahtzeevalue_game_rerolls(game: *mut Game) -> Result<c_int> {
+    Ok((*game).rerolls() as c_int)
+});
+
+export!(yahtzeevalue_game_points(game: *mut Game, player: c_int) -> Result<c_int> {
+    let p = (*game).players().get(player as usize).ok_or(ErrorKind::Range)?;
+    Ok(p.points as c_int)
+});
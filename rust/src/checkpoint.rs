@@ -0,0 +1,145 @@
+extern crate byteorder;
+
+use std::{fs, io};
+use std::io::{Read, Write};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crate::*;
+
+/// Checkpoints before the objective was recorded started with "YVCK".
+const MAGIC: &[u8; 4] = b"YVC2";
+
+/// State values of a partial computation: the states with `turn_count`
+/// at least `turn` are done, and the rest are to be computed.
+pub struct Checkpoint {
+    pub objective: Objective,
+    pub turn: u32,
+    /// Number of states evaluated so far.
+    pub done: usize,
+    pub reachable_only: bool,
+    pub state_value: Vec<f64>,
+}
+
+fn invalid(message: &str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
+
+fn read_name<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut name = vec![0; reader.read_u32::<LittleEndian>()? as usize];
+    reader.read_exact(&mut name)?;
+    Ok(name)
+}
+
+fn write_name<W: Write>(writer: &mut W, name: &str) -> Result<()> {
+    writer.write_u32::<LittleEndian>(name.len() as u32)?;
+    writer.write_all(name.as_bytes())?;
+    Ok(())
+}
+
+impl Checkpoint {
+    pub fn read(path: &str, rules: &RuleSet) -> Result<Checkpoint> {
+        let mut reader = io::BufReader::new(fs::File::open(path)?);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("Not a checkpoint file"));
+        }
+        let name = read_name(&mut reader)?;
+        let objective = String::from_utf8(read_name(&mut reader)?).ok().and_then(|n| Objective::by_name(&n))
+            .ok_or_else(|| invalid("Checkpoint has an unknown objective"))?;
+        let states = reader.read_u64::<LittleEndian>()? as usize;
+        if name != rules.name.as_bytes() || states != rules.state_count() {
+            return Err(invalid("Checkpoint is for different rules"));
+        }
+        let turn = reader.read_u32::<LittleEndian>()?;
+        let done = reader.read_u64::<LittleEndian>()? as usize;
+        let reachable_only = reader.read_u8()? != 0;
+        let mut state_value = vec![0.0; states];
+        reader.read_f64_into::<LittleEndian>(&mut state_value)?;
+        Ok(Checkpoint {
+            objective,
+            turn,
+            done,
+            reachable_only,
+            state_value,
+        })
+    }
+
+    /// Write the checkpoint to a temporary file and move it to `path`,
+    /// so an interrupted write keeps the previous checkpoint.
+    pub fn write(path: &str, rules: &RuleSet, objective: Objective, turn: u32, done: usize, reachable_only: bool,
+                 state_value: &[f64]) -> Result<()> {
+        let tmp_path = format!("{}.tmp", path);
+        {
+            let mut writer = io::BufWriter::new(fs::File::create(&tmp_path)?);
+            writer.write_all(MAGIC)?;
+            write_name(&mut writer, &rules.name)?;
+            write_name(&mut writer, &objective.name())?;
+            writer.write_u64::<LittleEndian>(state_value.len() as u64)?;
+            writer.write_u32::<LittleEndian>(turn)?;
+            writer.write_u64::<LittleEndian>(done as u64)?;
+            writer.write_u8(reachable_only as u8)?;
+            for &x in state_value {
+                writer.write_f64::<LittleEndian>(x)?;
+            }
+            writer.flush()?;
+        }
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testing::{self, TempFile};
+
+    /// Checkpoint of the expected scores of `rules` with the layers from
+    /// `turn` on done, as if killed after that layer.
+    fn write_partial(path: &str, rules: &RuleSet, turn: u32) {
+        let mut partial = testing::state_value(rules).to_vec();
+        let mut done = 0;
+        for (i, v) in partial.iter_mut().enumerate() {
+            if State::decode(rules, i as u32).turn_count() < turn {
+                *v = 0.0;
+            } else {
+                done += 1;
+            }
+        }
+        Checkpoint::write(path, rules, Objective::Expectation, turn, done, false, &partial).unwrap();
+    }
+
+    #[test]
+    fn resume_from_checkpoint() {
+        let rules = RuleSet::yahtzee().forced();
+        let file = TempFile::new("resume_test.ckpt");
+        write_partial(file.path(), &rules, 9);
+        let mut progress = Vec::new();
+        let resumed = compute_state_value_checkpointed(&rules, Objective::Expectation, 2, false, file.path(), true, |i, _| progress.push(i)).unwrap();
+        assert_eq!(resumed.iter().map(|v| v.to_bits()).collect::<Vec<_>>(),
+                   testing::state_value(&rules).iter().map(|v| v.to_bits()).collect::<Vec<_>>());
+        assert_eq!(progress[0], 0);
+        assert_eq!(progress[1], 1024);
+        let c = Checkpoint::read(file.path(), &rules).unwrap();
+        assert_eq!((c.turn, c.done), (0, rules.state_count()));
+    }
+
+    #[test]
+    fn checkpoint_of_other_rules() {
+        let rules = RuleSet::yahtzee().forced();
+        let file = TempFile::new("other_rules_test.ckpt");
+        write_partial(file.path(), &rules, 9);
+        assert!(Checkpoint::read(file.path(), &rules).is_ok());
+        assert!(Checkpoint::read(file.path(), &RuleSet::yatzy().forced()).is_err());
+    }
+
+    #[test]
+    fn checkpoint_of_other_objective() {
+        let rules = RuleSet::yahtzee().forced();
+        let file = TempFile::new("other_objective_test.ckpt");
+        write_partial(file.path(), &rules, 9);
+        assert_eq!(Checkpoint::read(file.path(), &rules).unwrap().objective, Objective::Expectation);
+        let resumed = compute_state_value_checkpointed(&rules, Objective::Exponential(0.01), 1, false, file.path(), true, |_, _| ());
+        assert!(resumed.is_err());
+    }
+}
+
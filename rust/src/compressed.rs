@@ -0,0 +1,176 @@
+extern crate byteorder;
+
+use std::io::Write;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use crate::*;
+use crate::header::write_values;
+use crate::lazy::LayeredValues;
+
+/// Memory for the values of the states left out, computed when needed.
+/// Each is computed from the stored layer after it, so the values
+/// dropped when it is full are quick to compute again.
+const COMPUTED_BUDGET: usize = 1 << 28;
+
+/// Whether the compressed table stores the value of the state: those of
+/// the even layers, counted by `turn_count`. The states of an odd layer
+/// are recomputed from the stored layer after it when needed, so a query
+/// costs at most one state's worth of work.
+pub fn precompute_state(_rules: &RuleSet, state: State) -> bool {
+    state.turn_count().is_multiple_of(2)
+}
+
+/// Write the values of the states chosen by `precompute_state` in the
+/// format read by `Store`: a `TableHeader` marked as compressed, a bitmap
+/// of the stored states, and the stored values in order of state as f64.
+pub fn write_compressed(path: &str, rules: &RuleSet, state_value: &[f64]) -> Result<()> {
+    write_values(path, rules, Objective::Expectation, Encoding::F64, true, state_value)
+}
+
+/// The bitmap of the states stored in a compressed table: bit i of word
+/// i / 64 is set if state i is stored, in little endian words.
+pub(crate) fn write_index<W: Write>(writer: &mut W, rules: &RuleSet) -> Result<()> {
+    let states = rules.state_count();
+    let mut word = 0u64;
+    for i in 0..states {
+        if precompute_state(rules, State::decode(rules, i as u32)) {
+            word |= 1 << (i % 64);
+        }
+        if i % 64 == 63 || i + 1 == states {
+            writer.write_u64::<LittleEndian>(word)?;
+            word = 0;
+        }
+    }
+    Ok(())
+}
+
+/// Where the stored values of a compressed table are, and the values of
+/// the other states computed so far.
+pub(crate) struct CompressedTable {
+    states: u32,
+    /// Number of stored states before each word of the bitmap.
+    rank: Vec<u32>,
+    computed: LayeredValues,
+}
+
+impl CompressedTable {
+    /// Bytes of the table after the header, the bitmap and the stored
+    /// values, for `states` states with the bitmap at the start of
+    /// `table`. None if `table` is too short for the bitmap.
+    pub(crate) fn size(states: u64, table: &[u8]) -> Option<u64> {
+        let words = states.div_ceil(64) as usize;
+        let index = table.get(..8 * words)?;
+        let stored: u64 = index.chunks(8).map(|w| LittleEndian::read_u64(w).count_ones() as u64).sum();
+        Some(8 * words as u64 + 8 * stored)
+    }
+
+    /// Locate the stored values of a table of `states` states, whose
+    /// header and size have been validated. `table` are the bytes after
+    /// the header.
+    pub(crate) fn new(states: u32, table: &[u8]) -> CompressedTable {
+        let words = (states as usize).div_ceil(64);
+        let mut rank = Vec::with_capacity(words);
+        let mut count = 0;
+        for word in table[..8 * words].chunks(8) {
+            rank.push(count);
+            count += LittleEndian::read_u64(word).count_ones();
+        }
+        CompressedTable {
+            states,
+            rank,
+            computed: LayeredValues::new(COMPUTED_BUDGET),
+        }
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        self.states
+    }
+
+    /// The value of the state `s` in turn `t`, computing it by `compute`
+    /// from the stored layer after it if it is not stored. `table` are
+    /// the bytes after the header.
+    pub(crate) fn value<F: FnOnce() -> f64>(&self, table: &[u8], s: u32, t: u32, compute: F) -> f64 {
+        let w = s as usize / 64;
+        let word = LittleEndian::read_u64(&table[8 * w..8 * w + 8]);
+        let bit = 1u64 << (s % 64);
+        if word & bit != 0 {
+            let k = self.rank[w] + (word & (bit - 1)).count_ones();
+            let i = 8 * (self.rank.len() + k as usize);
+            return LittleEndian::read_f64(&table[i..i+8]);
+        }
+        if let Some(v) = self.computed.get(t, s) {
+            return v;
+        }
+        let v = compute();
+        self.computed.insert(t, s, v);
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::compressed::CompressedTable;
+    use crate::header::HEADER_SIZE;
+    use crate::testing::{self, TempFile};
+    use crate::valuation::expected_state_value;
+
+    #[test]
+    fn compressed_table() {
+        let rules = RuleSet::yahtzee().forced();
+        let value = testing::state_value(&rules);
+        let full = TempFile::new("full_test.bin");
+        write_table(full.path(), &rules, Objective::Expectation, Encoding::F64, &value).unwrap();
+        let file = TempFile::new("compressed_test.bin");
+        write_compressed(file.path(), &rules, &value).unwrap();
+        // Only the even layers, about half of the states, and the bitmap.
+        assert!(std::fs::metadata(file.path()).unwrap().len() < std::fs::metadata(full.path()).unwrap().len() * 3 / 5);
+        let store = Store::with_rules(file.path(), rules.clone()).unwrap();
+        assert_eq!(store.len() as usize, value.len());
+        for i in (0..store.len()).rev() {
+            assert_eq!(store.get(i).unwrap().to_bits(), value[i as usize].to_bits());
+        }
+    }
+
+    #[test]
+    fn compressed_decisions() {
+        let rules = RuleSet::yahtzee().forced();
+        let value = testing::state_value(&rules);
+        let full_file = TempFile::new("full_decisions_test.bin");
+        write_table(full_file.path(), &rules, Objective::Expectation, Encoding::F64, &value).unwrap();
+        let file = TempFile::new("compressed_decisions_test.bin");
+        write_compressed(file.path(), &rules, &value).unwrap();
+        let full = Store::with_rules(full_file.path(), rules.clone()).unwrap();
+        let store = Store::with_rules(file.path(), rules.clone()).unwrap();
+        for i in (0..store.len()).step_by(7) {
+            for o in outcomes(&rules) {
+                assert_eq!(store.best_action(i, o, 0), full.best_action(i, o, 0));
+                assert_eq!(store.keep_first(i, o), full.keep_first(i, o));
+            }
+        }
+    }
+
+    #[test]
+    fn missing_state_from_next_layer() {
+        // The successors of a state left out are stored.
+        let rules = RuleSet::yahtzee().forced();
+        let value = testing::state_value(&rules);
+        let file = TempFile::new("next_layer_test.bin");
+        write_compressed(file.path(), &rules, &value).unwrap();
+        let bytes = std::fs::read(file.path()).unwrap();
+        let table = &bytes[HEADER_SIZE..];
+        let compressed = CompressedTable::new(rules.state_count() as u32, table);
+        let mut computed = 0;
+        for i in 0..rules.state_count() as u32 {
+            let state = State::decode(&rules, i);
+            let t = state.turn_count();
+            let v = compressed.value(table, i, t, || {
+                computed += 1;
+                let mut stored = |j| compressed.value(table, j, t + 1, || panic!("State {} is not stored", j));
+                expected_state_value(&rules, state, &mut stored, &mut Vec::new(), &mut Vec::new())
+            });
+            assert_eq!(v.to_bits(), value[i as usize].to_bits());
+            assert_eq!(precompute_state(&rules, state), t.is_multiple_of(2));
+        }
+        assert!(computed > 0);
+    }
+}
@@ -1,9 +1,5 @@
 pub const SIDES: usize = 6;
-pub const DICE_COUNT: usize = 6;
-pub const BONUS_COUNT: u32 = 4;
-pub const BONUS_LIMIT: u32 = BONUS_COUNT * (SIDES * (SIDES + 1) / 2) as u32;
-pub const BONUS: u32 = 50;
-pub const REROLL_COUNT: usize = 2;
+pub const MAX_DICE_COUNT: usize = 6;
 
 pub const SIDES_MASK: u8 = (1 << SIDES) - 1;
 
@@ -21,5 +17,15 @@ pub const R16: Comb = 8;
 pub const S23: Comb = 9;
 pub const CHANCE: Comb = 10;
 pub const YAHTZEE: Comb = 11;
-pub const COMB_COUNT: Comb = 12;
-pub const COMB_MASK: u16 = (1 << COMB_COUNT) - 1;
+
+// Rows of the classic five dice game
+pub const K3: Comb = 12;
+pub const K4: Comb = 13;
+pub const FULL_HOUSE: Comb = 14;
+pub const SM_STRAIGHT: Comb = 15;
+pub const LG_STRAIGHT: Comb = 16;
+pub const YAHTZEE50: Comb = 17;
+
+// Yatzy row of the Scandinavian five dice game
+pub const YATZY: Comb = 18;
+pub const COMB_COUNT: Comb = 19;
@@ -0,0 +1,377 @@
+extern crate byteorder;
+extern crate memmap;
+
+use std::{fs, io};
+use std::io::{Seek, SeekFrom, Write};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use crate::*;
+use crate::constants::*;
+use crate::layer::solve_layers;
+
+const MAGIC: &[u8; 4] = b"YVD2";
+
+/// Add `weight` times `src` shifted up by `shift` points to `dst`.
+fn add_shifted(dst: &mut Vec<f64>, src: &[f64], shift: usize, weight: f64) {
+    if dst.len() < src.len() + shift {
+        dst.resize(src.len() + shift, 0.0);
+    }
+    for (d, &s) in dst[shift..].iter_mut().zip(src.iter()) {
+        *d += weight * s;
+    }
+}
+
+/// Distribution of the points of the row chosen for `o`, with the row
+/// chosen to maximize expectation as in `Store::best_action`.
+fn stop_distribution<F, D>(rules: &RuleSet, state: State, o: Outcome, saved_rerolls: u32,
+                           state_value: &mut F, distribution: &D) -> Vec<f64>
+    where F: FnMut(u32) -> f64, D: StateVectors + ?Sized
+{
+    let mut best = -1f64;
+    let mut best_next = None;
+    actions(rules, state, o, |_action, next_state, points| {
+        let i = next_state.with_saved_rerolls(saved_rerolls).encode(rules);
+        let value = state_value(i) + points as f64;
+        if value > best {
+            best = value;
+            best_next = Some((i, points));
+        }
+    });
+    let mut res = Vec::new();
+    match best_next {
+        Some((i, points)) => add_shifted(&mut res, distribution.vector(i), points as usize, 1.0),
+        None => res.push(1.0),
+    }
+    res
+}
+
+/// Like `compute_subset_expectations`, averaging distributions instead.
+fn compute_subset_distributions(rules: &RuleSet, outcome_distribution: &mut [Vec<f64>]) {
+    let mut average = |o: Outcome| {
+        let mut res = Vec::new();
+        for s in o.successors() {
+            add_shifted(&mut res, &outcome_distribution[s.encode() as usize], 0, 1.0 / SIDES as f64);
+        }
+        outcome_distribution[o.encode() as usize] = res;
+    };
+    for n in (1..rules.dice_count).rev() {
+        for o in sub_outcomes(n) {
+            average(o);
+        }
+    }
+    average(Outcome::empty());
+}
+
+/// Distribution of the remaining points from `state` when each choice
+/// maximizes expectation according to `state_value`. `distribution`
+/// must hold the distributions of the successors of `state`.
+pub fn state_distribution<F, D>(rules: &RuleSet, state: State, state_value: &mut F, distribution: &D) -> Vec<f64>
+    where F: FnMut(u32) -> f64, D: StateVectors + ?Sized
+{
+    if state.done(rules) {
+        return vec![1.0];
+    }
+    let rerolls = state.rerolls(rules);
+    let mut roll_value = Vec::new();
+    let mut scratch = Vec::new();
+    let mut outcome_distribution = vec![Vec::new(); max_outcome_encoding(rules) + 1];
+    for o in outcomes(rules) {
+        outcome_distribution[o.encode() as usize] = stop_distribution(rules, state, o, 0, state_value, distribution);
+    }
+    compute_subset_distributions(rules, &mut outcome_distribution);
+    for r in 1..(rerolls + 1) {
+        // Choices with r rerolls left are made by the values with r - 1 left.
+        compute_roll_values(rules, state, state_value, r - 1, &mut roll_value, &mut scratch);
+        let saved = (r as u32).min(rules.max_saved_rerolls);
+        let mut next_distribution = vec![Vec::new(); outcome_distribution.len()];
+        for o in outcomes(rules) {
+            let i = o.encode() as usize;
+            let mut keep = o;
+            choose_reroll(&mut keep, &roll_value);
+            let stop = if saved > 0 { stop_value(rules, state, o, saved, state_value) } else { f64::NEG_INFINITY };
+            next_distribution[i] = if stop > roll_value[keep.encode() as usize] {
+                stop_distribution(rules, state, o, saved, state_value, distribution)
+            } else {
+                outcome_distribution[keep.encode() as usize].clone()
+            };
+        }
+        compute_subset_distributions(rules, &mut next_distribution);
+        outcome_distribution = next_distribution;
+    }
+    let mut res = Vec::new();
+    let mut denominator = 0;
+    for o in outcomes(rules) {
+        denominator += o.multiplicity();
+    }
+    for o in outcomes(rules) {
+        let weight = o.multiplicity() as f64 / denominator as f64;
+        add_shifted(&mut res, &outcome_distribution[o.encode() as usize], 0, weight);
+    }
+    while res.last() == Some(&0.0) {
+        res.pop();
+    }
+    res
+}
+
+/// The distribution of a state for `solve_layers`, None if unreachable.
+fn layer_distribution<F: FnMut(u32) -> f64>(rules: &RuleSet, i: u32, state: State, state_value: &mut F,
+                                            next: &Layer) -> Option<Vec<f64>> {
+    if state_value(i).is_nan() {
+        None
+    } else {
+        Some(state_distribution(rules, state, state_value, next))
+    }
+}
+
+/// Compute the distribution of the remaining points from every state
+/// under the policy maximizing expectation. States with NaN value are
+/// unreachable and get an empty distribution.
+///
+/// Every distribution is returned in memory, so this is only practical
+/// for small rule sets such as the forced order variants. Use
+/// `compute_distribution_file` for the others.
+pub fn compute_distribution<F: FnMut(u32) -> f64, P: FnMut(usize, usize)>(rules: &RuleSet, state_value: &mut F, pi: P) -> Vec<Vec<f64>> {
+    let mut distribution = vec![Vec::new(); rules.state_count()];
+    solve_layers(rules, 0, None, |i, s, next| layer_distribution(rules, i, s, state_value, next), |layer| {
+        for (i, v) in layer.iter() {
+            distribution[i as usize] = v.to_vec();
+        }
+        Ok(())
+    }, pi).unwrap();
+    distribution
+}
+
+/// Compute the distributions as `compute_distribution` and write them to
+/// `path` in the format read by `DistributionStore`. Only two layers of
+/// states are kept in memory, and the others are written when done.
+pub fn compute_distribution_file<F: FnMut(u32) -> f64, P: FnMut(usize, usize)>(rules: &RuleSet, state_value: &mut F,
+                                                                               path: &str, pi: P) -> Result<()> {
+    let mut writer = VectorWriter::create(path, MAGIC, rules.state_count())?;
+    solve_layers(rules, 0, None, |i, s, next| layer_distribution(rules, i, s, state_value, next), |layer| {
+        layer.iter().try_for_each(|(i, v)| writer.push(i, v))
+    }, pi)?;
+    writer.finish()
+}
+
+/// Bytes before the ranges of the vectors: a magic number and the
+/// number of states.
+const VECTORS_HEADER_SIZE: usize = 12;
+
+/// Writer of one vector per state in any order: a magic number, the
+/// number of states, the start and end of each vector in values, and
+/// finally the values, all little endian. The ranges are kept in memory
+/// and written last.
+pub(crate) struct VectorWriter {
+    writer: io::BufWriter<fs::File>,
+    ranges: Vec<(u64, u64)>,
+    /// Values written so far.
+    len: u64,
+}
+
+impl VectorWriter {
+    pub(crate) fn create(path: &str, magic: &[u8; 4], states: usize) -> Result<VectorWriter> {
+        let mut writer = io::BufWriter::new(fs::File::create(path)?);
+        writer.write_all(magic)?;
+        writer.write_u64::<LittleEndian>(states as u64)?;
+        writer.seek(SeekFrom::Start((VECTORS_HEADER_SIZE + 16 * states) as u64))?;
+        Ok(VectorWriter { writer, ranges: vec![(0, 0); states], len: 0 })
+    }
+
+    /// Write the vector of state `s`. States without a vector are
+    /// unreachable.
+    pub(crate) fn push(&mut self, s: u32, vector: &[f64]) -> Result<()> {
+        for &x in vector {
+            self.writer.write_f64::<LittleEndian>(x)?;
+        }
+        self.ranges[s as usize] = (self.len, self.len + vector.len() as u64);
+        self.len += vector.len() as u64;
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<()> {
+        self.writer.seek(SeekFrom::Start(VECTORS_HEADER_SIZE as u64))?;
+        for &(start, end) in self.ranges.iter() {
+            self.writer.write_u64::<LittleEndian>(start)?;
+            self.writer.write_u64::<LittleEndian>(end)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Write the vectors of every state with a `VectorWriter`.
+pub(crate) fn write_vectors(path: &str, magic: &[u8; 4], vectors: &[Vec<f64>]) -> Result<()> {
+    let mut writer = VectorWriter::create(path, magic, vectors.len())?;
+    for (s, v) in vectors.iter().enumerate() {
+        writer.push(s as u32, v)?;
+    }
+    writer.finish()
+}
+
+/// Reader of the files written by `VectorWriter`.
+pub(crate) struct VectorTable {
+    mmap: memmap::Mmap,
+    states: u32,
+}
+
+impl VectorTable {
+    pub(crate) fn open(path: &str, magic: &[u8; 4]) -> Result<VectorTable> {
+        let file = fs::File::open(path)?;
+        let mmap = unsafe { memmap::Mmap::map(&file)? };
+        if mmap.len() < VECTORS_HEADER_SIZE || &mmap[0..4] != magic {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Wrong kind of table file").into());
+        }
+        let states = LittleEndian::read_u64(&mmap[4..12]);
+        if states > u32::MAX as u64 || mmap.len() < VECTORS_HEADER_SIZE + 16 * states as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Truncated table file").into());
+        }
+        Ok(VectorTable {
+            mmap,
+            states: states as u32,
+        })
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        self.states
+    }
+
+    /// Start and end in the file of the vector of the state.
+    /// Unreachable states have an empty vector.
+    fn range(&self, s: u32) -> Result<(usize, usize)> {
+        if s >= self.states {
+            return Err(ErrorKind::Range.into());
+        }
+        let data = VECTORS_HEADER_SIZE + 16 * self.states as usize;
+        let i = VECTORS_HEADER_SIZE + 16 * s as usize;
+        let a = LittleEndian::read_u64(&self.mmap[i..i+8]) as usize;
+        let b = LittleEndian::read_u64(&self.mmap[i+8..i+16]) as usize;
+        if a == b {
+            return Err(ErrorKind::Unreachable.into());
+        }
+        if b < a || data + 8 * b > self.mmap.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Truncated table file").into());
+        }
+        Ok((data + 8 * a, data + 8 * b))
+    }
+
+    pub(crate) fn get(&self, s: u32) -> Result<Vec<f64>> {
+        let (a, b) = self.range(s)?;
+        let mut res = vec![0.0; (b - a) / 8];
+        LittleEndian::read_f64_into(&self.mmap[a..b], &mut res);
+        Ok(res)
+    }
+
+    /// Element `k` of the vector of the state, or `default` past its end.
+    pub(crate) fn get_element(&self, s: u32, k: usize, default: f64) -> Result<f64> {
+        let (a, b) = self.range(s)?;
+        let i = a + 8 * k;
+        if i >= b {
+            return Ok(default);
+        }
+        Ok(LittleEndian::read_f64(&self.mmap[i..i+8]))
+    }
+}
+
+/// Write distributions in the format read by `DistributionStore`.
+pub fn write_distribution(path: &str, distribution: &[Vec<f64>]) -> Result<()> {
+    write_vectors(path, MAGIC, distribution)
+}
+
+/// Reader of the distributions of remaining points written by
+/// `write_distribution`. Points are counted as in `Store::get`.
+pub struct DistributionStore {
+    table: VectorTable,
+}
+
+impl DistributionStore {
+    pub fn new(path: &str) -> Result<DistributionStore> {
+        Ok(DistributionStore {
+            table: VectorTable::open(path, MAGIC)?,
+        })
+    }
+
+    pub fn len(&self) -> u32 {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Probability of each number of remaining points, starting at 0.
+    pub fn distribution(&self, s: u32) -> Result<Vec<f64>> {
+        self.table.get(s)
+    }
+
+    /// The `p` quantile: the least number of points x such that the
+    /// remaining points are at most x with probability at least `p`.
+    pub fn quantile(&self, s: u32, p: f64) -> Result<u32> {
+        let distribution = self.distribution(s)?;
+        let mut acc = 0.0;
+        for (points, &q) in distribution.iter().enumerate() {
+            acc += q;
+            if acc >= p {
+                return Ok(points as u32);
+            }
+        }
+        Ok(distribution.len() as u32 - 1)
+    }
+
+    /// Probability of getting at least `points` remaining points.
+    pub fn prob_at_least(&self, s: u32, points: u32) -> Result<f64> {
+        let distribution = self.distribution(s)?;
+        Ok(distribution.iter().skip(points as usize).sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testing::{self, TempFile};
+
+    #[test]
+    fn forced_order_distribution() {
+        let rules = RuleSet::yatzy().forced();
+        let value = testing::reachable_state_value(&rules);
+        let distribution = compute_distribution(&rules, &mut |i| value[i as usize], |_, _| ());
+        for (v, d) in value.iter().zip(distribution.iter()) {
+            if v.is_nan() {
+                assert!(d.is_empty());
+                continue;
+            }
+            let mean: f64 = d.iter().enumerate().map(|(x, p)| x as f64 * p).sum();
+            assert!((d.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+            assert!((mean - v).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn distribution_store() {
+        let rules = testing::small_rules().forced();
+        let value = testing::reachable_state_value(&rules);
+        let distribution = compute_distribution(&rules, &mut |i| value[i as usize], |_, _| ());
+        let file = TempFile::new("distribution_test.bin");
+        write_distribution(file.path(), &distribution).unwrap();
+        let store = DistributionStore::new(file.path()).unwrap();
+        assert_eq!(store.distribution(0).unwrap(), distribution[0]);
+        let median = store.quantile(0, 0.5).unwrap();
+        assert!(store.prob_at_least(0, median).unwrap() >= 0.5);
+        assert!(store.prob_at_least(0, median + 1).unwrap() < 0.5);
+        assert_eq!(store.prob_at_least(0, 0).unwrap(), distribution[0].iter().sum::<f64>());
+        let unreachable = value.iter().position(|v| v.is_nan()).unwrap();
+        assert!(matches!(store.distribution(unreachable as u32), Err(Error { kind: ErrorKind::Unreachable })));
+    }
+
+    #[test]
+    fn distribution_file() {
+        // Written a layer at a time, with the same distributions.
+        let rules = testing::small_rules().forced();
+        let value = testing::reachable_state_value(&rules);
+        let distribution = compute_distribution(&rules, &mut |i| value[i as usize], |_, _| ());
+        let file = TempFile::new("distribution_file_test.bin");
+        compute_distribution_file(&rules, &mut |i| value[i as usize], file.path(), |_, _| ()).unwrap();
+        let store = DistributionStore::new(file.path()).unwrap();
+        for (i, d) in distribution.iter().enumerate() {
+            assert_eq!(store.distribution(i as u32).unwrap_or_default(), *d);
+        }
+    }
+}
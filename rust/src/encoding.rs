@@ -0,0 +1,144 @@
+extern crate byteorder;
+
+use std::io;
+use std::io::Write;
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+use crate::*;
+
+/// Steps per point of `Encoding::U16`.
+const U16_SCALE: f64 = 32.0;
+/// `Encoding::U16` value of unreachable states.
+const U16_NAN: u16 = u16::MAX;
+
+/// How the values of a state value table are stored, as recorded in its
+/// `TableHeader`. `Store` tells the encodings of headerless tables apart
+/// by the size of the table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// Little endian f64, as computed.
+    F64,
+    /// Little endian f32, half the size.
+    F32,
+    /// Little endian u16 of `State::upper_bound_points` minus the value
+    /// in steps of 1/32 point, a quarter of the size. Differences above
+    /// 2047 points are clamped.
+    U16,
+}
+
+impl Encoding {
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "f64" => Some(Encoding::F64),
+            "f32" => Some(Encoding::F32),
+            "u16" => Some(Encoding::U16),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Encoding::F64 => "f64",
+            Encoding::F32 => "f32",
+            Encoding::U16 => "u16",
+        }
+    }
+
+    /// Bytes per state.
+    pub fn size(&self) -> usize {
+        match *self {
+            Encoding::F64 => 8,
+            Encoding::F32 => 4,
+            Encoding::U16 => 2,
+        }
+    }
+
+    /// The encoding of a table of `bytes` bytes for `states` states.
+    pub fn detect(bytes: usize, states: usize) -> Option<Self> {
+        [Encoding::F64, Encoding::F32, Encoding::U16].iter().cloned().find(|e| e.size() * states == bytes)
+    }
+
+    /// Default file name of the table for these rules in this encoding.
+    pub fn table_path(&self, rules: &RuleSet) -> String {
+        match *self {
+            Encoding::F64 => rules.table_path(),
+            _ => rules.table_path().replace(".bin", &format!(".{}.bin", self.name())),
+        }
+    }
+
+    /// Append the encoding of the value of state `s` to `writer`.
+    pub fn write<W: Write>(&self, writer: &mut W, rules: &RuleSet, s: u32, value: f64) -> io::Result<()> {
+        match *self {
+            Encoding::F64 => writer.write_f64::<LittleEndian>(value),
+            Encoding::F32 => writer.write_f32::<LittleEndian>(value as f32),
+            Encoding::U16 => {
+                let q = if value.is_nan() {
+                    U16_NAN
+                } else {
+                    let ub = State::decode(rules, s).upper_bound_points(rules) as f64;
+                    ((ub - value) * U16_SCALE).round().clamp(0.0, (U16_NAN - 1) as f64) as u16
+                };
+                writer.write_u16::<LittleEndian>(q)
+            },
+        }
+    }
+
+    /// The value of state `s` from its encoding at the start of `bytes`.
+    pub fn read(&self, bytes: &[u8], rules: &RuleSet, s: u32) -> f64 {
+        match *self {
+            Encoding::F64 => LittleEndian::read_f64(bytes),
+            Encoding::F32 => LittleEndian::read_f32(bytes) as f64,
+            Encoding::U16 => {
+                let q = LittleEndian::read_u16(bytes);
+                if q == U16_NAN {
+                    return f64::NAN;
+                }
+                let ub = State::decode(rules, s).upper_bound_points(rules) as f64;
+                ub - q as f64 / U16_SCALE
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testing::{self, TempFile};
+
+    /// Write the reachable values of Yahtzee in `encoding` and check that
+    /// they read back within `tolerance`.
+    fn check_encoding(encoding: Encoding, tolerance: f64) {
+        let rules = RuleSet::yahtzee().forced();
+        let value = testing::reachable_state_value(&rules);
+        let file = TempFile::new(&format!("{}_test.bin", encoding.name()));
+        write_table(file.path(), &rules, Objective::Expectation, encoding, &value).unwrap();
+        let store = Store::with_rules(file.path(), rules.clone()).unwrap();
+        assert_eq!(store.encoding(), encoding);
+        assert_eq!(store.len() as usize, value.len());
+        for (i, v) in value.iter().enumerate() {
+            match store.get(i as u32) {
+                Ok(x) => assert!((x - v).abs() <= tolerance),
+                Err(_) => assert!(v.is_nan()),
+            }
+        }
+    }
+
+    #[test]
+    fn f64_table() {
+        check_encoding(Encoding::F64, 0.0);
+    }
+
+    #[test]
+    fn f32_table() {
+        check_encoding(Encoding::F32, 1e-4);
+    }
+
+    #[test]
+    fn u16_table() {
+        check_encoding(Encoding::U16, 1.0 / 64.0);
+    }
+
+    #[test]
+    fn encoding_table_path() {
+        assert_eq!(Encoding::U16.table_path(&RuleSet::yahtzee().forced()), "state_value_yahtzee-forced.u16.bin");
+    }
+}
@@ -9,6 +9,23 @@ pub enum ErrorKind {
     Io(io::Error),
     FileNotFound,
     GameOver,
+    Unreachable,
+    /// The file is not a state value table of a known format.
+    BadMagic,
+    /// The table is for other rules or another objective.
+    RuleMismatch,
+    /// The table is truncated or its values do not match its checksum.
+    Corrupt,
+    /// The move is not allowed at this point of the turn.
+    WrongPhase,
+    /// The roll does not have the right number of dice or lacks the kept dice.
+    IllegalRoll,
+    /// The dice to keep are not in the roll.
+    IllegalKeep,
+    /// The row cannot be chosen for the roll.
+    IllegalRow,
+    /// The table is of a format version this version cannot read.
+    UnsupportedVersion,
 }
 
 #[derive(Debug)]
@@ -16,9 +33,9 @@ pub struct Error {
     pub kind: ErrorKind,
 }
 
-impl Into<Error> for ErrorKind {
-    fn into(self) -> Error {
-        Error { kind: self }
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error { kind }
     }
 }
 
@@ -30,6 +47,15 @@ impl fmt::Display for Error {
             ErrorKind::Io(ref e) => write!(f, "{}", e),
             ErrorKind::FileNotFound => write!(f, "File not found."),
             ErrorKind::GameOver => write!(f, "Game over."),
+            ErrorKind::Unreachable => write!(f, "State is not reachable."),
+            ErrorKind::BadMagic => write!(f, "Not a state value table."),
+            ErrorKind::RuleMismatch => write!(f, "Table is for different rules."),
+            ErrorKind::Corrupt => write!(f, "Table is corrupt."),
+            ErrorKind::WrongPhase => write!(f, "Move not allowed at this point of the turn."),
+            ErrorKind::IllegalRoll => write!(f, "Roll does not have the right dice."),
+            ErrorKind::IllegalKeep => write!(f, "Dice to keep are not in the roll."),
+            ErrorKind::IllegalRow => write!(f, "Row cannot be chosen for the roll."),
+            ErrorKind::UnsupportedVersion => write!(f, "Table format version not supported."),
         }
     }
 }
@@ -42,6 +68,15 @@ impl CError for Error {
             ErrorKind::Io(_) => 3,
             ErrorKind::FileNotFound => 4,
             ErrorKind::GameOver => 5,
+            ErrorKind::Unreachable => 6,
+            ErrorKind::BadMagic => 7,
+            ErrorKind::RuleMismatch => 8,
+            ErrorKind::Corrupt => 9,
+            ErrorKind::WrongPhase => 10,
+            ErrorKind::IllegalRoll => 11,
+            ErrorKind::IllegalKeep => 12,
+            ErrorKind::IllegalRow => 13,
+            ErrorKind::UnsupportedVersion => 14,
         }
     }
 }
@@ -0,0 +1,249 @@
+use rand::Rng;
+use crate::*;
+use crate::constants::SIDES;
+
+/// The score sheet of a player.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Player {
+    pub state: State,
+    /// Points scored so far, including bonuses.
+    pub points: u32,
+}
+
+/// Where the current turn is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// The dice not in `kept` are to be rolled. `kept` is empty at the
+    /// start of a turn.
+    Roll { kept: Outcome },
+    /// Some of `outcome` may be kept and the rest rerolled if `rerolls`
+    /// is positive, or `outcome` is put on a row.
+    Choose { outcome: Outcome, rerolls: usize },
+    /// Every player has filled every row.
+    Done,
+}
+
+/// The rules of play: players take turns rolling the dice, rerolling
+/// some of them, and putting the roll on an open row. Every move is
+/// checked, and a move that is not allowed gives an error and leaves
+/// the game as it was.
+///
+/// A row may be chosen after any roll of the turn. With
+/// `RuleSet::max_saved_rerolls`, the rerolls not used are saved for
+/// later turns.
+#[derive(Debug, Clone)]
+pub struct Game {
+    rules: RuleSet,
+    players: Vec<Player>,
+    player_index: usize,
+    phase: Phase,
+    /// Rerolls left in the turn, used when the dice are rolled.
+    rerolls: usize,
+}
+
+impl Game {
+    pub fn new(rules: RuleSet, player_count: usize) -> Self {
+        assert!(player_count > 0);
+        let rerolls = State::initial().rerolls(&rules);
+        Game {
+            rules,
+            players: vec![Player { state: State::initial(), points: 0 }; player_count],
+            player_index: 0,
+            phase: Phase::Roll { kept: Outcome::empty() },
+            rerolls,
+        }
+    }
+
+    /// A game at the start of the turn of `players[player_index]`, such
+    /// as one being played elsewhere.
+    pub fn with_players(rules: RuleSet, players: Vec<Player>, player_index: usize) -> Self {
+        assert!(player_index < players.len());
+        let state = players[player_index].state;
+        let (phase, rerolls) = if state.done(&rules) {
+            (Phase::Done, 0)
+        } else {
+            (Phase::Roll { kept: Outcome::empty() }, state.rerolls(&rules))
+        };
+        Game { rules, players, player_index, phase, rerolls }
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    pub fn players(&self) -> &[Player] {
+        &self.players
+    }
+
+    /// Index of the player whose turn it is.
+    pub fn player_index(&self) -> usize {
+        self.player_index
+    }
+
+    pub fn player(&self) -> &Player {
+        &self.players[self.player_index]
+    }
+
+    /// State of the player whose turn it is.
+    pub fn state(&self) -> State {
+        self.player().state
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn done(&self) -> bool {
+        self.phase == Phase::Done
+    }
+
+    /// Rerolls left in the turn after the current roll.
+    pub fn rerolls(&self) -> usize {
+        match self.phase {
+            Phase::Choose { rerolls, .. } => rerolls,
+            _ => self.rerolls,
+        }
+    }
+
+    /// Roll the dice, giving `outcome`, which must have all dice and
+    /// contain the kept dice.
+    pub fn roll(&mut self, outcome: Outcome) -> Result<()> {
+        let kept = match self.phase {
+            Phase::Roll { kept } => kept,
+            Phase::Choose { .. } => return Err(ErrorKind::WrongPhase.into()),
+            Phase::Done => return Err(ErrorKind::GameOver.into()),
+        };
+        if outcome.dice_count() != self.rules.dice_count || !kept.is_subset(&outcome) {
+            return Err(ErrorKind::IllegalRoll.into());
+        }
+        self.phase = Phase::Choose { outcome, rerolls: self.rerolls };
+        Ok(())
+    }
+
+    /// Roll the dice not kept at random.
+    pub fn roll_random<R: Rng>(&mut self, rng: &mut R) -> Result<Outcome> {
+        let mut outcome = match self.phase {
+            Phase::Roll { kept } => kept,
+            Phase::Choose { .. } => return Err(ErrorKind::WrongPhase.into()),
+            Phase::Done => return Err(ErrorKind::GameOver.into()),
+        };
+        for _ in outcome.dice_count()..self.rules.dice_count {
+            outcome.histogram[rng.gen_range(0, SIDES)] += 1;
+        }
+        self.roll(outcome)?;
+        Ok(outcome)
+    }
+
+    /// Keep the dice in `keep` and reroll the others.
+    pub fn keep(&mut self, keep: Outcome) -> Result<()> {
+        let (outcome, rerolls) = match self.phase {
+            Phase::Choose { outcome, rerolls } => (outcome, rerolls),
+            Phase::Roll { .. } => return Err(ErrorKind::WrongPhase.into()),
+            Phase::Done => return Err(ErrorKind::GameOver.into()),
+        };
+        if rerolls == 0 {
+            return Err(ErrorKind::WrongPhase.into());
+        }
+        if !keep.is_subset(&outcome) {
+            return Err(ErrorKind::IllegalKeep.into());
+        }
+        self.rerolls = rerolls - 1;
+        self.phase = Phase::Roll { kept: keep };
+        Ok(())
+    }
+
+    /// Put the roll on the row of `action`, which must be one of
+    /// `actions`. Returns the points scored, and passes the dice to the
+    /// next player.
+    pub fn choose(&mut self, action: Action) -> Result<u32> {
+        let (outcome, rerolls) = match self.phase {
+            Phase::Choose { outcome, rerolls } => (outcome, rerolls),
+            Phase::Roll { .. } => return Err(ErrorKind::WrongPhase.into()),
+            Phase::Done => return Err(ErrorKind::GameOver.into()),
+        };
+        let mut chosen = None;
+        actions(&self.rules, self.state(), outcome, |a, next_state, points| {
+            if a == action {
+                chosen = Some((next_state, points));
+            }
+        });
+        let (next_state, points) = chosen.ok_or(ErrorKind::IllegalRow)?;
+        let saved = (rerolls as u32).min(self.rules.max_saved_rerolls);
+        let player = &mut self.players[self.player_index];
+        player.state = next_state.with_saved_rerolls(saved);
+        player.points += points;
+        self.player_index = (self.player_index + 1) % self.players.len();
+        // Every player has the same number of turns.
+        if self.state().done(&self.rules) {
+            self.phase = Phase::Done;
+        } else {
+            self.rerolls = self.state().rerolls(&self.rules);
+            self.phase = Phase::Roll { kept: Outcome::empty() };
+        }
+        Ok(points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testing::{self, error_code};
+
+    fn roll(histogram: [u8; constants::SIDES]) -> Outcome {
+        Outcome { histogram }
+    }
+
+    #[test]
+    fn game_moves() {
+        let mut game = Game::new(testing::small_rules(), 2);
+        assert_eq!(error_code(game.keep(Outcome::empty())), Some(10));
+        assert_eq!(error_code(game.roll(roll([1, 1, 0, 0, 0, 0]))), Some(11));
+        game.roll(roll([2, 0, 0, 0, 0, 1])).unwrap();
+        assert_eq!(error_code(game.roll(roll([2, 0, 0, 0, 0, 1]))), Some(10));
+        assert_eq!(error_code(game.keep(roll([0, 0, 0, 0, 0, 2]))), Some(12));
+        game.keep(roll([2, 0, 0, 0, 0, 0])).unwrap();
+        assert_eq!(error_code(game.roll(roll([0, 0, 0, 0, 0, 3]))), Some(11));
+        game.roll(roll([3, 0, 0, 0, 0, 0])).unwrap();
+        assert_eq!(game.rerolls(), 1);
+        assert_eq!(game.choose(Action::Side(0)).unwrap(), 3);
+        assert_eq!(game.player_index(), 1);
+        assert_eq!(game.rerolls(), 2);
+    }
+
+    #[test]
+    fn random_game() {
+        let rules = testing::small_rules();
+        let mut game = Game::new(rules.clone(), 2);
+        let mut rng = rand::thread_rng();
+        let mut points = vec![0, 0];
+        while !game.done() {
+            let player = game.player_index();
+            let state = game.state();
+            let outcome = game.roll_random(&mut rng).unwrap();
+            if state.has_side(0) {
+                assert_eq!(error_code(game.choose(Action::Side(0))), Some(13));
+            }
+            let mut first = None;
+            actions(&rules, state, outcome, |action, _, p| {
+                first = first.or(Some((action, p)));
+            });
+            let (action, p) = first.unwrap();
+            assert_eq!(game.choose(action).unwrap(), p);
+            points[player] += p;
+        }
+        assert_eq!(game.players().iter().map(|p| p.points).collect::<Vec<_>>(), points);
+        assert!(game.players().iter().all(|p| p.state.done(&rules)));
+        assert_eq!(error_code(game.roll(roll([3, 0, 0, 0, 0, 0]))), Some(5));
+    }
+
+    #[test]
+    fn resume_game() {
+        let rules = testing::small_rules();
+        let done = State::decode(&rules, rules.state_count() as u32 - 1);
+        assert!(done.done(&rules));
+        let players = vec![Player { state: State::initial(), points: 0 }, Player { state: done, points: 20 }];
+        assert!(Game::with_players(rules.clone(), players.clone(), 1).done());
+        let resumed = Game::with_players(rules, players, 0);
+        assert_eq!((resumed.phase(), resumed.rerolls()), (Phase::Roll { kept: Outcome::empty() }, 2));
+    }
+}
@@ -0,0 +1,335 @@
+extern crate byteorder;
+
+use std::{fs, io};
+use std::io::{Seek, SeekFrom, Write};
+use byteorder::{ByteOrder, LittleEndian};
+use crate::*;
+use crate::compressed::{CompressedTable, write_index};
+
+pub(crate) const MAGIC: &[u8; 4] = b"YVTB";
+/// Format version written by `write_table`. Version 2 added the bitmap
+/// of the stored states to compressed tables, and version 3 the hash of
+/// the rules.
+pub const TABLE_VERSION: u32 = 3;
+/// Bytes before the values, a multiple of 8 so the values stay aligned.
+pub(crate) const HEADER_SIZE: usize = 104;
+/// Bytes for each of the rule set and objective names, padded with zeros.
+const NAME_SIZE: usize = 32;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// The header of a state value table, telling what the values are for
+/// and how they are stored. Little endian, laid out as:
+///
+/// | Offset | Size | Field                                  |
+/// |--------|------|----------------------------------------|
+/// | 0      | 4    | Magic bytes "YVTB"                     |
+/// | 4      | 4    | Format version                         |
+/// | 8      | 1    | Value encoding: 0 f64, 1 f32, 2 u16    |
+/// | 9      | 1    | 1 if compressed, 0 otherwise           |
+/// | 10     | 6    | Reserved, zero                         |
+/// | 16     | 8    | Number of states                       |
+/// | 24     | 8    | FNV-1a hash of the values              |
+/// | 32     | 32   | Name of the rule set, e.g. "yatzy"     |
+/// | 64     | 32   | Name of the objective, e.g. "exp0.01"  |
+/// | 96     | 8    | FNV-1a hash of the rules, see below    |
+///
+/// The hash of the rules covers the parameters that change the values,
+/// so a table is not taken for one of other rules of the same name.
+/// The values follow the header. Compressed tables, written by
+/// `write_compressed`, only have the values of the states chosen by
+/// `precompute_state`, after a bitmap of those states with a bit for
+/// each state in 64-bit words.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableHeader {
+    pub version: u32,
+    pub rules: String,
+    pub objective: Objective,
+    pub encoding: Encoding,
+    pub compressed: bool,
+    pub states: u64,
+    pub checksum: u64,
+    pub rules_hash: u64,
+}
+
+impl TableHeader {
+    /// Read the header at the start of `bytes`. Returns `None` for tables
+    /// without a header, written before the header was introduced.
+    pub fn read(bytes: &[u8]) -> Result<Option<Self>> {
+        if !bytes.starts_with(MAGIC) {
+            return Ok(None);
+        }
+        if bytes.len() < HEADER_SIZE {
+            return Err(ErrorKind::Corrupt.into());
+        }
+        let version = LittleEndian::read_u32(&bytes[4..8]);
+        if version != TABLE_VERSION {
+            return Err(ErrorKind::UnsupportedVersion.into());
+        }
+        let encoding = match bytes[8] {
+            0 => Encoding::F64,
+            1 => Encoding::F32,
+            2 => Encoding::U16,
+            _ => return Err(ErrorKind::Corrupt.into()),
+        };
+        let compressed = match bytes[9] {
+            0 => false,
+            1 => true,
+            _ => return Err(ErrorKind::Corrupt.into()),
+        };
+        let name = |field: &[u8]| {
+            let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+            std::str::from_utf8(&field[..end]).map(|s| s.to_owned())
+        };
+        let rules = name(&bytes[32..32 + NAME_SIZE])?;
+        let objective = Objective::by_name(&name(&bytes[64..64 + NAME_SIZE])?).ok_or(ErrorKind::Corrupt)?;
+        Ok(Some(TableHeader {
+            version,
+            rules,
+            objective,
+            encoding,
+            compressed,
+            states: LittleEndian::read_u64(&bytes[16..24]),
+            checksum: LittleEndian::read_u64(&bytes[24..32]),
+            rules_hash: LittleEndian::read_u64(&bytes[96..104]),
+        }))
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut bytes = [0u8; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(MAGIC);
+        LittleEndian::write_u32(&mut bytes[4..8], self.version);
+        bytes[8] = match self.encoding {
+            Encoding::F64 => 0,
+            Encoding::F32 => 1,
+            Encoding::U16 => 2,
+        };
+        bytes[9] = self.compressed as u8;
+        LittleEndian::write_u64(&mut bytes[16..24], self.states);
+        LittleEndian::write_u64(&mut bytes[24..32], self.checksum);
+        LittleEndian::write_u64(&mut bytes[96..104], self.rules_hash);
+        let objective = self.objective.name();
+        for &(offset, name) in [(32, &self.rules[..]), (64, &objective[..])].iter() {
+            if name.len() > NAME_SIZE {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Name too long for table header").into());
+            }
+            bytes[offset..offset + name.len()].copy_from_slice(name.as_bytes());
+        }
+        writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Check that the header is for `rules` and `objective`, and that
+    /// `values`, the bytes after the header, have the size it was written
+    /// for. The values themselves are checked by `verify`.
+    pub fn validate(&self, rules: &RuleSet, objective: Objective, values: &[u8]) -> Result<()> {
+        if self.rules != rules.name || self.rules_hash != rules_hash(rules) || self.objective.name() != objective.name()
+            || self.states != rules.state_count() as u64 {
+            return Err(ErrorKind::RuleMismatch.into());
+        }
+        let size = if self.compressed {
+            CompressedTable::size(self.states, values)
+        } else {
+            Some(self.states * self.encoding.size() as u64)
+        };
+        if size != Some(values.len() as u64) {
+            return Err(ErrorKind::Corrupt.into());
+        }
+        Ok(())
+    }
+
+    /// Check that `values`, the bytes after the header, match the
+    /// checksum. This reads the whole table, so it is not done when a
+    /// table is opened.
+    pub fn verify(&self, values: &[u8]) -> Result<()> {
+        if checksum(FNV_OFFSET, values) != self.checksum {
+            return Err(ErrorKind::Corrupt.into());
+        }
+        Ok(())
+    }
+}
+
+/// Hash of the parameters of `rules` that change the values of a table.
+fn rules_hash(rules: &RuleSet) -> u64 {
+    let mut bytes = Vec::new();
+    for &x in [rules.dice_count as u32, rules.reroll_count as u32, rules.bonus_count, rules.bonus_limit, rules.bonus,
+               rules.relative_upper as u32, rules.yahtzee_bonus, rules.joker as u32, rules.max_saved_rerolls,
+               rules.forced_order as u32].iter() {
+        bytes.extend_from_slice(&x.to_le_bytes());
+    }
+    bytes.extend(rules.combs().iter().map(|&c| c as u8));
+    checksum(FNV_OFFSET, &bytes)
+}
+
+fn checksum(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, &b| (h ^ b as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Hashes what is written through it, for the header written after the
+/// values.
+struct ChecksumWriter<W> {
+    inner: W,
+    hash: u64,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hash = checksum(self.hash, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Write a table of state values for `objective` in the given encoding,
+/// with a `TableHeader` in front.
+pub fn write_table(path: &str, rules: &RuleSet, objective: Objective, encoding: Encoding, state_value: &[f64]) -> Result<()> {
+    write_values(path, rules, objective, encoding, false, state_value)
+}
+
+/// Write the values of the states chosen by `precompute_state`, or of
+/// every state if not `compressed`, behind a `TableHeader`.
+pub(crate) fn write_values(path: &str, rules: &RuleSet, objective: Objective, encoding: Encoding,
+                           compressed: bool, state_value: &[f64]) -> Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(&[0; HEADER_SIZE])?;
+    let mut writer = ChecksumWriter { inner: io::BufWriter::new(file), hash: FNV_OFFSET };
+    if compressed {
+        write_index(&mut writer, rules)?;
+    }
+    for (i, &v) in state_value.iter().enumerate() {
+        if !compressed || precompute_state(rules, State::decode(rules, i as u32)) {
+            encoding.write(&mut writer, rules, i as u32, v)?;
+        }
+    }
+    let header = TableHeader {
+        version: TABLE_VERSION,
+        rules: rules.name.clone(),
+        objective,
+        encoding,
+        compressed,
+        states: state_value.len() as u64,
+        checksum: writer.hash,
+        rules_hash: rules_hash(rules),
+    };
+    let mut file = writer.inner.into_inner().map_err(io::Error::from)?;
+    file.seek(SeekFrom::Start(0))?;
+    header.write(&mut file)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testing::{self, TempFile, error_code};
+
+    /// Write the expected scores of Yahtzee to `file`, compressed or as
+    /// F32, and return the rules.
+    fn write_yahtzee(file: &TempFile, compressed: bool) -> RuleSet {
+        let rules = RuleSet::yahtzee().forced();
+        let value = testing::state_value(&rules);
+        if compressed {
+            write_compressed(file.path(), &rules, &value).unwrap();
+        } else {
+            write_table(file.path(), &rules, Objective::Expectation, Encoding::F32, &value).unwrap();
+        }
+        rules
+    }
+
+    #[test]
+    fn table_header() {
+        let file = TempFile::new("header_test.bin");
+        let rules = write_yahtzee(&file, false);
+        let value = testing::state_value(&rules);
+        let store = Store::with_rules(file.path(), rules.clone()).unwrap();
+        assert_eq!(store.encoding(), Encoding::F32);
+        assert_eq!(store.len() as usize, value.len());
+        assert_eq!(store.get(0).unwrap(), value[0] as f32 as f64);
+        let bytes = std::fs::read(file.path()).unwrap();
+        let header = TableHeader::read(&bytes).unwrap().unwrap();
+        assert_eq!((header.version, &header.rules[..], header.states), (TABLE_VERSION, "yahtzee-forced", value.len() as u64));
+        assert!(!header.compressed);
+    }
+
+    #[test]
+    fn header_mismatch() {
+        let file = TempFile::new("mismatch_test.bin");
+        let rules = write_yahtzee(&file, false);
+        assert_eq!(error_code(Store::with_rules(file.path(), RuleSet::yatzy().forced())), Some(8));
+        assert_eq!(error_code(Store::with_objective(file.path(), rules, Objective::Exponential(0.01))), Some(8));
+    }
+
+    #[test]
+    fn corrupt_table() {
+        // Opened without reading the values, which verify checks.
+        let file = TempFile::new("corrupt_test.bin");
+        let rules = write_yahtzee(&file, false);
+        assert!(Store::with_rules(file.path(), rules.clone()).unwrap().verify().is_ok());
+        let mut bytes = std::fs::read(file.path()).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        std::fs::write(file.path(), &bytes).unwrap();
+        let store = Store::with_rules(file.path(), rules).unwrap();
+        assert_eq!(error_code(store.verify()), Some(9));
+    }
+
+    #[test]
+    fn truncated_table() {
+        let file = TempFile::new("truncated_test.bin");
+        let rules = write_yahtzee(&file, false);
+        let bytes = std::fs::read(file.path()).unwrap();
+        std::fs::write(file.path(), &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(error_code(Store::with_rules(file.path(), rules)), Some(9));
+    }
+
+    #[test]
+    fn other_version() {
+        let file = TempFile::new("version_test.bin");
+        let rules = write_yahtzee(&file, false);
+        let mut bytes = std::fs::read(file.path()).unwrap();
+        bytes[4] = 1;
+        std::fs::write(file.path(), &bytes).unwrap();
+        assert_eq!(error_code(Store::with_rules(file.path(), rules)), Some(14));
+    }
+
+    #[test]
+    fn rules_of_same_name() {
+        // Rules of the same name with other parameters are told apart.
+        let rules = testing::small_rules();
+        let file = TempFile::new("same_name_test.bin");
+        write_table(file.path(), &rules, Objective::Expectation, Encoding::F64, &testing::state_value(&rules)).unwrap();
+        assert!(Store::with_rules(file.path(), rules.clone()).is_ok());
+        let mut other = rules;
+        other.bonus += 1;
+        assert_eq!(error_code(Store::with_rules(file.path(), other)), Some(8));
+    }
+
+    #[test]
+    fn headerless_table_size() {
+        // Without a header, the size must fit an encoding
+        let file = TempFile::new("headerless_test.bin");
+        let rules = write_yahtzee(&file, false);
+        let bytes = std::fs::read(file.path()).unwrap();
+        std::fs::write(file.path(), &bytes[4..]).unwrap();
+        assert_eq!(error_code(Store::with_rules(file.path(), rules)), Some(7));
+    }
+
+    #[test]
+    fn compressed_table_header() {
+        // Checked against the rules and objective like a full table.
+        let file = TempFile::new("compressed_header_test.bin");
+        let rules = write_yahtzee(&file, true);
+        let mut bytes = std::fs::read(file.path()).unwrap();
+        assert!(TableHeader::read(&bytes).unwrap().unwrap().compressed);
+        assert_eq!(error_code(Store::with_objective(file.path(), rules.clone(), Objective::Exponential(0.01))), Some(8));
+        assert_eq!(error_code(Store::with_rules(file.path(), RuleSet::yatzy().forced())), Some(8));
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        std::fs::write(file.path(), &bytes).unwrap();
+        assert_eq!(error_code(Store::with_rules(file.path(), rules).unwrap().verify()), Some(9));
+    }
+}
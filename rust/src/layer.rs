@@ -0,0 +1,135 @@
+use crate::*;
+
+/// Vectors of states by their encoding, such as the distributions of the
+/// successors of a state.
+pub trait StateVectors {
+    /// The vector of the state with encoding `s`, empty if there is none.
+    fn vector(&self, s: u32) -> &[f64];
+}
+
+impl StateVectors for [Vec<f64>] {
+    fn vector(&self, s: u32) -> &[f64] {
+        &self[s as usize]
+    }
+}
+
+impl StateVectors for Vec<Vec<f64>> {
+    fn vector(&self, s: u32) -> &[f64] {
+        &self[s as usize]
+    }
+}
+
+/// The vectors of one layer of states, those with equal `turn_count`.
+#[derive(Debug, Clone, Default)]
+pub struct Layer {
+    /// In increasing order.
+    states: Vec<u32>,
+    vectors: Vec<Vec<f64>>,
+}
+
+impl Layer {
+    pub fn new() -> Self {
+        Layer::default()
+    }
+
+    /// Add the vector of `s`, which must be above the states added so far.
+    pub fn push(&mut self, s: u32, vector: Vec<f64>) {
+        assert!(self.states.last().is_none_or(|&t| t < s));
+        self.states.push(s);
+        self.vectors.push(vector);
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &[f64])> {
+        self.states.iter().cloned().zip(self.vectors.iter().map(|v| &v[..]))
+    }
+}
+
+impl StateVectors for Layer {
+    fn vector(&self, s: u32) -> &[f64] {
+        match self.states.binary_search(&s) {
+            Ok(k) => &self.vectors[k],
+            Err(_) => &[],
+        }
+    }
+}
+
+/// The encodings of the states by `turn_count`, from turn `turn` on,
+/// decoding each state once. The layers before `turn` are left empty.
+/// Only the states marked in `reachable` are included, if given.
+pub(crate) fn states_by_turn(rules: &RuleSet, turn: u32, reachable: Option<&[bool]>) -> Vec<Vec<u32>> {
+    let mut layers = vec![Vec::new(); rules.turns() as usize + 1];
+    for i in 0..rules.state_count() as u32 {
+        if reachable.is_none_or(|r| r[i as usize]) {
+            let t = State::decode(rules, i).turn_count();
+            if t >= turn {
+                layers[t as usize].push(i);
+            }
+        }
+    }
+    layers
+}
+
+/// Compute a vector for every state from turn `turn` on, one layer of
+/// states with equal `turn_count` at a time from the last, as
+/// `compute_state_value_threads` does. Every successor of a state is in
+/// the next layer, so only that layer is kept in memory. `evaluate` is
+/// given the encoding of a state, the state and the next layer, and
+/// returns the vector of the state or None to leave it out. `layer_done`
+/// is given each layer when it is complete. Only the states marked in
+/// `reachable` are evaluated, if given. Returns the layer of `turn`.
+pub(crate) fn solve_layers<E, L, P>(rules: &RuleSet, turn: u32, reachable: Option<&[bool]>,
+                                    mut evaluate: E, mut layer_done: L, mut pi: P) -> Result<Layer>
+    where E: FnMut(u32, State, &Layer) -> Option<Vec<f64>>, L: FnMut(&Layer) -> Result<()>, P: FnMut(usize, usize)
+{
+    let layers = states_by_turn(rules, turn, reachable);
+    let states = layers.iter().map(|l| l.len()).sum();
+    pi(0, states);
+    let mut done = 0usize;
+    let mut next = Layer::new();
+    for t in (turn..rules.turns() + 1).rev() {
+        let mut layer = Layer::new();
+        for &i in layers[t as usize].iter() {
+            if let Some(v) = evaluate(i, State::decode(rules, i), &next) {
+                layer.push(i, v);
+            }
+            done += 1;
+            if done.is_multiple_of(1 << 10) {
+                pi(done, states);
+            }
+        }
+        layer_done(&layer)?;
+        next = layer;
+    }
+    if !done.is_multiple_of(1 << 10) {
+        pi(done, states);
+    }
+    Ok(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::layer::states_by_turn;
+    use crate::testing;
+
+    #[test]
+    fn layers_by_turn() {
+        let rules = testing::small_rules();
+        let layers = states_by_turn(&rules, 1, None);
+        assert!(layers[0].is_empty());
+        let count = (0..rules.state_count() as u32).filter(|&i| State::decode(&rules, i).turn_count() >= 1).count();
+        assert_eq!(layers.iter().map(|l| l.len()).sum::<usize>(), count);
+        for (t, layer) in layers.iter().enumerate() {
+            assert!(layer.windows(2).all(|w| w[0] < w[1]));
+            assert!(layer.iter().all(|&i| State::decode(&rules, i).turn_count() as usize == t));
+        }
+    }
+}
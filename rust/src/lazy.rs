@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use crate::*;
+use crate::valuation::expected_state_value;
+
+/// Estimated bytes per memoized state, including hash table overhead.
+const ENTRY_SIZE: usize = 24;
+
+/// Expected scores computed on demand instead of read from a table, for
+/// rules without a precomputed table.
+///
+/// The value of a state is computed from the values of its successors,
+/// which are computed in turn down to the states where the game is done,
+/// and all of them are kept for later queries. States late in the game
+/// are therefore quick, while the initial state needs about every state
+/// of the rules. When the kept values exceed the memory budget, the
+/// values of the latest turn are dropped first, as they are the quickest
+/// to compute again.
+///
+/// Values are bit-identical to those of `compute_state_value`.
+pub struct LazyValue {
+    rules: RuleSet,
+    values: LayeredValues,
+    /// Tables to compute a state with, one pair for each state being
+    /// computed, as the recursion goes no deeper than the number of turns.
+    buffers: Mutex<Vec<(Vec<f64>, Vec<f64>)>>,
+}
+
+impl LazyValue {
+    /// Keep at most about `budget` bytes of values.
+    pub fn new(rules: RuleSet, budget: usize) -> Self {
+        LazyValue {
+            rules,
+            values: LayeredValues::new(budget),
+            buffers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    /// Number of states whose value is kept.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every kept value.
+    pub fn clear(&self) {
+        self.values.clear();
+    }
+
+    /// Returns the expected score from the state with encoding `s`.
+    /// Usable wherever a `state_value` closure is expected, as
+    /// `&mut |s| lazy.value(s)`.
+    pub fn value(&self, s: u32) -> f64 {
+        let state = State::decode(&self.rules, s);
+        if state.done(&self.rules) {
+            return 0.0;
+        }
+        let t = state.turn_count();
+        if let Some(v) = self.values.get(t, s) {
+            return v;
+        }
+        // Not locked while computing, as that needs the successors.
+        let (mut outcome_value, mut scratch) = self.buffers.lock().unwrap_or_else(|e| e.into_inner()).pop().unwrap_or_default();
+        let v = expected_state_value(&self.rules, state, &mut |j| self.value(j), &mut outcome_value, &mut scratch);
+        self.buffers.lock().unwrap_or_else(|e| e.into_inner()).push((outcome_value, scratch));
+        self.values.insert(t, s, v);
+        v
+    }
+}
+
+/// Values of states kept by turn count within a memory budget. When they
+/// exceed it, the values of the latest turn are dropped first, as they
+/// are the quickest to compute again.
+pub(crate) struct LayeredValues {
+    /// Maximum number of kept values.
+    capacity: usize,
+    /// Kept values by turn count of the state.
+    layers: Mutex<Vec<HashMap<u32, f64>>>,
+}
+
+impl LayeredValues {
+    /// Keep at most about `budget` bytes of values.
+    pub(crate) fn new(budget: usize) -> Self {
+        LayeredValues {
+            capacity: (budget / ENTRY_SIZE).max(1),
+            layers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.lock().iter().map(|l| l.len()).sum()
+    }
+
+    pub(crate) fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// The kept value of the state `s` in turn `t`.
+    pub(crate) fn get(&self, t: u32, s: u32) -> Option<f64> {
+        self.lock().get(t as usize).and_then(|l| l.get(&s)).cloned()
+    }
+
+    pub(crate) fn insert(&self, t: u32, s: u32, v: f64) {
+        let t = t as usize;
+        let mut layers = self.lock();
+        if layers.len() <= t {
+            layers.resize_with(t + 1, HashMap::new);
+        }
+        layers[t].insert(s, v);
+        let mut kept: usize = layers.iter().map(|l| l.len()).sum();
+        while kept > self.capacity {
+            // Latest turn other than that of s, or that of s if it is all
+            // that is left.
+            let drop = (0..layers.len()).rev().find(|&u| u != t && !layers[u].is_empty()).unwrap_or(t);
+            kept -= layers[drop].len();
+            layers[drop] = HashMap::new();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<HashMap<u32, f64>>> {
+        self.layers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testing;
+
+    #[test]
+    fn lazy_value() {
+        let rules = testing::small_rules();
+        let value = testing::state_value(&rules);
+        let lazy = LazyValue::new(rules.clone(), 1 << 30);
+        for i in (0..rules.state_count() as u32).rev() {
+            assert_eq!(lazy.value(i).to_bits(), value[i as usize].to_bits());
+        }
+    }
+
+    #[test]
+    fn lazy_value_budget() {
+        // Dropping values to stay within the budget gives the same values.
+        let rules = testing::small_rules();
+        let value = testing::state_value(&rules);
+        let budget = 24 * rules.state_count() / 2;
+        let lazy = LazyValue::new(rules.clone(), budget);
+        assert_eq!(lazy.value(0).to_bits(), value[0].to_bits());
+        assert!(lazy.len() <= rules.state_count() / 2);
+        assert_eq!(lazy.value(1).to_bits(), value[1].to_bits());
+    }
+}
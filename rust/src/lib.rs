@@ -7,20 +7,73 @@ pub use err::{Error, ErrorKind, Result};
 
 pub mod constants;
 
+mod rules;
+pub use rules::RuleSet;
+
 mod outcome;
 pub use outcome::{Outcome, outcomes, sub_outcomes, max_outcome_encoding};
 
 mod state;
-pub use state::State;
+pub use state::{State, StateDisplay};
 
 mod scoring;
-pub use scoring::{Action, actions};
+pub use scoring::{Action, actions, next_row, comb_scores};
+
+mod objective;
+pub use objective::Objective;
 
 mod valuation;
-pub use valuation::{compute_state_value, compute_outcome_values, compute_subset_expectations, compute_reroll_value, choose_reroll};
+pub use valuation::{compute_state_value, compute_state_value_threads, compute_reachable_state_value,
+                    compute_state_value_checkpointed, compute_objective_value, reachable_states, reachable_states_from,
+                    compute_outcome_values, compute_subset_expectations, compute_reroll_value, choose_reroll, rank_rerolls,
+                    compute_roll_values, choose_keep, stop_value, compute_roll_values_by, choose_keep_by, stop_value_by,
+                    Valuation, HitProbabilities};
+
+mod encoding;
+pub use encoding::Encoding;
+
+mod header;
+pub use header::{TableHeader, TABLE_VERSION, write_table};
+
+mod compressed;
+pub use compressed::{precompute_state, write_compressed};
 
 mod store;
-pub use store::Store;
+pub use store::{Store, RankedAction};
+
+mod lazy;
+pub use lazy::LazyValue;
+
+mod game;
+pub use game::{Game, Player, Phase};
+
+mod strategy;
+pub use strategy::{Strategy, StateValueFn, TableStrategy, GreedyStrategy, RandomStrategy, HeuristicStrategy,
+                   SoftmaxStrategy, DEFAULT_TEMPERATURE, play_turn, strategy_by_name, strategy_uses_state_value};
+
+mod options;
+pub use options::{PlayOptions, LAZY_BUDGET};
+
+mod checkpoint;
+pub use checkpoint::Checkpoint;
+
+mod layer;
+pub use layer::{Layer, StateVectors};
+
+mod distribution;
+pub use distribution::{DistributionStore, compute_distribution, compute_distribution_file, state_distribution,
+                       write_distribution};
+
+mod utility;
+pub use utility::{compute_utility_value, compute_successor_utility_value, state_utility_value, utility_objective, win_utility, threshold_utility,
+                  compute_mean_std_value};
+
+mod target;
+pub use target::{TargetStore, compute_target_value, compute_target_value_file, state_target_value, write_target_value,
+                 remaining_target};
+
+#[cfg(test)]
+mod testing;
 
 #[cfg(test)]
 mod tests {
@@ -41,26 +94,27 @@ mod tests {
 
     #[test]
     fn outcome_encode_decode() {
-        for o in outcomes() {
+        for o in outcomes(&RuleSet::super_yahtzee()) {
             assert_eq!(o, Outcome::decode(o.encode()));
         }
     }
 
     #[test]
     fn state_encode_decode() {
+        let rules = RuleSet::super_yahtzee();
         for i in 0..2228 {
-            let s = State::decode(i * 1000);
-            assert_eq!(s, State::decode(s.encode()));
+            let s = State::decode(&rules, i * 1000);
+            assert_eq!(s, State::decode(&rules, s.encode(&rules)));
         }
     }
 
     #[test]
     fn state_initial_encode() {
-        assert_eq!(State::initial().encode(), 0);
+        assert_eq!(State::initial().encode(&RuleSet::super_yahtzee()), 0);
     }
 
     #[test]
     fn state_initial_upper_bound() {
-        assert_eq!(State::initial().upper_bound_points(), 580);
+        assert_eq!(State::initial().upper_bound_points(&RuleSet::super_yahtzee()), 580);
     }
 }
@@ -0,0 +1,84 @@
+use crate::*;
+
+/// What the state values maximize instead of plain expectation.
+///
+/// The value of a state is a number of points such that adding points
+/// to every outcome adds them to the value. Choices therefore only depend
+/// on the state, and a table for any objective is used like one of
+/// expected scores: the best row maximizes value plus points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    /// The expected remaining points.
+    Expectation,
+    /// The certainty equivalent of the exponential utility
+    /// `-exp(-a x) / a` of the remaining points `x`. Risk averse when
+    /// `a` is positive and risk seeking when it is negative; `a` must
+    /// not be zero, and values beyond about 1 in size overflow.
+    Exponential(f64),
+    /// Mean minus `lambda` times standard deviation of the remaining
+    /// points, where every choice maximizes it given the later choices.
+    MeanStd(f64),
+}
+
+impl Objective {
+    /// Name as used in file names, e.g. "exp0.01" or "meansd0.5".
+    pub fn name(&self) -> String {
+        match *self {
+            Objective::Expectation => "expectation".to_owned(),
+            Objective::Exponential(a) => format!("exp{}", a),
+            Objective::MeanStd(lambda) => format!("meansd{}", lambda),
+        }
+    }
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        if name == "expectation" {
+            return Some(Objective::Expectation);
+        }
+        if let Some(a) = name.strip_prefix("exp") {
+            return a.parse().ok().filter(|&a: &f64| a != 0.0 && a.is_finite()).map(Objective::Exponential);
+        }
+        if let Some(lambda) = name.strip_prefix("meansd") {
+            return lambda.parse().ok().filter(|l: &f64| l.is_finite()).map(Objective::MeanStd);
+        }
+        None
+    }
+
+    /// Default file name of the state value table for these rules and
+    /// this objective.
+    pub fn table_path(&self, rules: &RuleSet) -> String {
+        match *self {
+            Objective::Expectation => rules.table_path(),
+            _ => rules.table_path().replace(".bin", &format!("_{}.bin", self.name())),
+        }
+    }
+
+    /// Utility of `x` points for `Exponential`, increasing in `x`.
+    pub(crate) fn exponential_utility(a: f64, x: f64) -> f64 {
+        -(-a * x).exp() / a
+    }
+
+    /// Inverse of `exponential_utility`.
+    pub(crate) fn exponential_certainty_equivalent(a: f64, u: f64) -> f64 {
+        -(-a * u).ln() / a
+    }
+
+    /// Value for `MeanStd` of points with the given mean and second moment.
+    pub(crate) fn mean_std_value(lambda: f64, mean: f64, second_moment: f64) -> f64 {
+        mean - lambda * (second_moment - mean * mean).max(0.0).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testing;
+
+    #[test]
+    fn objective_names() {
+        assert_eq!(Objective::by_name("exp0.1"), Some(Objective::Exponential(0.1)));
+        assert_eq!(Objective::by_name(&Objective::MeanStd(0.5).name()), Some(Objective::MeanStd(0.5)));
+        assert_eq!(Objective::by_name("exp0"), None);
+        let rules = testing::small_rules().forced();
+        assert_eq!(Objective::Exponential(0.1).table_path(&rules), "state_value_small-forced_exp0.1.bin");
+    }
+}
@@ -0,0 +1,61 @@
+use crate::*;
+
+/// Memory for the expected scores computed with --lazy.
+pub const LAZY_BUDGET: usize = 1 << 30;
+
+/// The command line options shared by the programs that play games: the
+/// name of a rule set for `RuleSet::by_name`, by default Super Yahtzee,
+/// and --lazy. With --lazy, expected scores are computed when needed
+/// instead of read from the table, which is only quick for small rules.
+#[derive(Debug, Clone)]
+pub struct PlayOptions {
+    pub rules: RuleSet,
+    pub lazy: bool,
+}
+
+impl PlayOptions {
+    /// Take --lazy and the first rule set name out of `args`, leaving the
+    /// other arguments.
+    pub fn take(args: &mut Vec<String>) -> Self {
+        let lazy = args.iter().any(|a| a == "--lazy");
+        args.retain(|a| a != "--lazy");
+        let rules = match args.iter().position(|a| RuleSet::by_name(a).is_some()) {
+            Some(i) => RuleSet::by_name(&args.remove(i)).unwrap(),
+            None => RuleSet::super_yahtzee(),
+        };
+        PlayOptions { rules, lazy }
+    }
+
+    /// The values of the states for `objective`, NaN for the states not
+    /// in a reachable-only table. Only expected scores are computed with
+    /// --lazy, so it panics for other objectives.
+    pub fn state_value(&self, objective: Objective) -> Result<Box<StateValueFn>> {
+        if self.lazy {
+            assert!(objective == Objective::Expectation, "Only expected scores are computed with --lazy");
+            let lazy_value = LazyValue::new(self.rules.clone(), LAZY_BUDGET);
+            return Ok(Box::new(move |i| lazy_value.value(i)));
+        }
+        let store = Store::with_objective(&objective.table_path(&self.rules), self.rules.clone(), objective)?;
+        Ok(Box::new(move |i| store.get(i).unwrap_or(f64::NAN)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn play_options() {
+        let mut args: Vec<String> = ["--games", "5", "yatzy-forced", "--lazy", "greedy"].iter().map(|a| a.to_string()).collect();
+        let options = PlayOptions::take(&mut args);
+        assert_eq!(options.rules, RuleSet::yatzy().forced());
+        assert!(options.lazy);
+        assert_eq!(args, ["--games", "5", "greedy"]);
+    }
+
+    #[test]
+    fn default_play_options() {
+        let options = PlayOptions::take(&mut Vec::new());
+        assert_eq!((options.rules, options.lazy), (RuleSet::super_yahtzee(), false));
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt;
 
 use crate::constants::*;
+use crate::rules::RuleSet;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Outcome {
@@ -25,45 +26,51 @@ impl Outcome {
     pub fn encode(&self) -> u32 {
         let mut r = 0;
         let mut a = 1;
-        for d in 0..SIDES {
-            r += a * self.histogram[d] as u32;
-            a *= (DICE_COUNT + 1) as u32;
+        for &c in self.histogram.iter() {
+            r += a * c as u32;
+            a *= (MAX_DICE_COUNT + 1) as u32;
         }
         r
     }
 
     pub fn decode(mut v: u32) -> Self {
         let mut histogram = [0u8; SIDES];
-        for d in 0..SIDES {
-            histogram[d] = (v % (DICE_COUNT + 1) as u32) as u8;
-            v /= (DICE_COUNT + 1) as u32;
+        for c in histogram.iter_mut() {
+            *c = (v % (MAX_DICE_COUNT + 1) as u32) as u8;
+            v /= (MAX_DICE_COUNT + 1) as u32;
         }
-        Outcome { histogram: histogram }
+        Outcome { histogram }
+    }
+
+    pub fn dice_count(&self) -> usize {
+        self.histogram.iter().map(|&c| c as usize).sum()
+    }
+
+    /// Whether every die of `self` is also in `other`.
+    pub fn is_subset(&self, other: &Outcome) -> bool {
+        self.histogram.iter().zip(other.histogram.iter()).all(|(a, b)| a <= b)
     }
 
     pub fn multiplicity(&self) -> usize {
-        let mut fac = [0; DICE_COUNT];
-        fac[0] = 1;
+        let mut fac = [1; MAX_DICE_COUNT + 1];
         for i in 1..fac.len() {
-            fac[i] = fac[i - 1] * (i + 1);
+            fac[i] = fac[i - 1] * i;
         }
-        let mut res = fac[fac.len() - 1];
-        for i in 0..SIDES {
-            if self.histogram[i] > 0 {
-                res /= fac[self.histogram[i] as usize - 1];
-            }
+        let mut res = fac[self.dice_count()];
+        for &c in self.histogram.iter() {
+            res /= fac[c as usize];
         }
         res
     }
 
-    pub fn predecessors(&self) -> OutcomePredecessorIterator {
+    pub fn predecessors(&self) -> OutcomePredecessorIterator<'_> {
         OutcomePredecessorIterator {
             outcome: self,
             next: 0,
         }
     }
 
-    pub fn successors(&self) -> OutcomeSuccessorIterator {
+    pub fn successors(&self) -> OutcomeSuccessorIterator<'_> {
         OutcomeSuccessorIterator {
             outcome: self,
             next: 0,
@@ -82,11 +89,11 @@ impl fmt::Display for Outcome {
     }
 }
 
-pub fn max_outcome_encoding() -> usize {
-    outcomes().map(|o| o.encode()).max().unwrap() as usize
+pub fn max_outcome_encoding(rules: &RuleSet) -> usize {
+    outcomes(rules).map(|o| o.encode()).max().unwrap() as usize
 }
 
-impl <'a> Iterator for OutcomePredecessorIterator<'a> {
+impl Iterator for OutcomePredecessorIterator<'_> {
     type Item = Outcome;
 
     fn next(&mut self) -> Option<Outcome> {
@@ -99,11 +106,11 @@ impl <'a> Iterator for OutcomePredecessorIterator<'a> {
         let mut histogram = self.outcome.histogram;
         histogram[self.next] -= 1;
         self.next += 1;
-        Some(Outcome { histogram: histogram })
+        Some(Outcome { histogram })
     }
 }
 
-impl <'a> Iterator for OutcomeSuccessorIterator<'a> {
+impl Iterator for OutcomeSuccessorIterator<'_> {
     type Item = Outcome;
 
     fn next(&mut self) -> Option<Outcome> {
@@ -113,7 +120,7 @@ impl <'a> Iterator for OutcomeSuccessorIterator<'a> {
         let mut histogram = self.outcome.histogram;
         histogram[self.next] += 1;
         self.next += 1;
-        Some(Outcome { histogram: histogram })
+        Some(Outcome { histogram })
     }
 }
 
@@ -129,8 +136,8 @@ impl OutcomeIterator {
     }
 }
 
-pub fn outcomes() -> OutcomeIterator {
-    OutcomeIterator::new(DICE_COUNT as u8)
+pub fn outcomes(rules: &RuleSet) -> OutcomeIterator {
+    OutcomeIterator::new(rules.dice_count as u8)
 }
 
 pub fn sub_outcomes(dice_count: usize) -> OutcomeIterator {
@@ -165,14 +172,16 @@ impl Iterator for OutcomeIterator {
 mod tests {
     use crate::*;
     use crate::constants::*;
+    use crate::rules::RuleSet;
     #[test]
     fn test_multiplicity() {
+        let rules = RuleSet::super_yahtzee();
         let mut sum = 0;
-        for o in outcomes() {
+        for o in outcomes(&rules) {
             sum += o.multiplicity();
         }
         let mut exp = 1;
-        for _ in 0..DICE_COUNT {
+        for _ in 0..rules.dice_count {
             exp *= SIDES;
         }
         assert_eq!(sum, exp);
@@ -0,0 +1,240 @@
+use crate::constants::*;
+use crate::scoring::{default_comb_name, default_comb_symbol};
+
+/// The parameters of a Yahtzee variant.
+///
+/// Rows are identified by their `Comb` constant. `combs` lists the rows
+/// of the variant in score sheet order, and the position of a row in
+/// `combs` is its bit in the state encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleSet {
+    pub name: String,
+    pub dice_count: usize,
+    pub reroll_count: usize,
+    pub bonus_count: u32,
+    pub bonus_limit: u32,
+    pub bonus: u32,
+    /// Upper section is written relative to `bonus_count` of each side.
+    pub relative_upper: bool,
+    /// Points for each additional Yahtzee after the Yahtzee row was
+    /// filled with a Yahtzee. When nonzero, `State::yahtzee_bonus` is
+    /// part of the state encoding.
+    pub yahtzee_bonus: u32,
+    /// A Yahtzee rolled after the Yahtzee row is filled must go in the
+    /// upper section if possible, and otherwise scores full points in
+    /// any combination row.
+    pub joker: bool,
+    /// Unused rerolls are saved for later turns, up to this many.
+    pub max_saved_rerolls: u32,
+    /// Rows are filled top to bottom, so the only choice is which dice
+    /// to keep. The state is then just the turn and the upper score.
+    pub forced_order: bool,
+    combs: Vec<Comb>,
+    comb_mask: u32,
+    /// `combs[i] == i` for all i, so combinations need no reordering.
+    combs_in_order: bool,
+    /// Row names and symbols as written on the score sheet, indexed by `Comb`.
+    comb_names: Vec<String>,
+    comb_symbols: Vec<char>,
+}
+
+impl RuleSet {
+    pub fn new(name: &str, dice_count: usize, reroll_count: usize, bonus_count: u32, bonus: u32, combs: &[Comb]) -> Self {
+        assert!(dice_count <= MAX_DICE_COUNT);
+        let mut comb_mask = 0;
+        for &c in combs {
+            assert!(c < COMB_COUNT);
+            comb_mask |= 1 << c;
+        }
+        RuleSet {
+            name: name.to_owned(),
+            dice_count,
+            reroll_count,
+            bonus_count,
+            bonus_limit: bonus_count * (SIDES * (SIDES + 1) / 2) as u32,
+            bonus,
+            relative_upper: false,
+            yahtzee_bonus: 0,
+            joker: false,
+            max_saved_rerolls: 0,
+            forced_order: false,
+            combs: combs.to_vec(),
+            comb_mask,
+            combs_in_order: combs.iter().enumerate().all(|(i, &c)| i == c),
+            comb_names: (0..COMB_COUNT).map(|c| default_comb_name(c).to_owned()).collect(),
+            comb_symbols: (0..COMB_COUNT).map(default_comb_symbol).collect(),
+        }
+    }
+
+    /// Six dice, twelve combination rows and a bonus of 50 for
+    /// getting at least four of each side.
+    pub fn super_yahtzee() -> Self {
+        let mut rules = RuleSet::new("super-yahtzee", 6, 2, 4, 50, &[
+            S2, S22, S222, S3, S4, S33, R15, R26, R16, S23, CHANCE, YAHTZEE]);
+        rules.relative_upper = true;
+        rules
+    }
+
+    /// Super Yahtzee where unused rerolls are saved for later turns,
+    /// as in Maxi Yatzy.
+    pub fn maxi_yatzy() -> Self {
+        let mut rules = RuleSet::super_yahtzee();
+        rules.name = "maxi-yatzy".to_owned();
+        rules.max_saved_rerolls = 3;
+        rules
+    }
+
+    /// Five dice and seven combination rows with US scoring:
+    /// a bonus of 35 at 63, 100 for each extra Yahtzee and Joker rules.
+    pub fn yahtzee() -> Self {
+        let mut rules = RuleSet::new("yahtzee", 5, 2, 3, 35, &[
+            K3, K4, FULL_HOUSE, SM_STRAIGHT, LG_STRAIGHT, YAHTZEE50, CHANCE]);
+        rules.yahtzee_bonus = 100;
+        rules.joker = true;
+        rules
+    }
+
+    /// Five dice, nine combination rows scored by the sum of the dice
+    /// used, and a bonus of 50 at 63, as in the Scandinavian game.
+    pub fn yatzy() -> Self {
+        let mut rules = RuleSet::new("yatzy", 5, 2, 3, 50, &[
+            S2, S22, S3, S4, R15, R26, S23, CHANCE, YATZY]);
+        rules.set_comb_label(S2, "One Pair", 'P');
+        rules.set_comb_label(R15, "Small Straight", 's');
+        rules.set_comb_label(R26, "Large Straight", 'S');
+        rules.set_comb_label(S23, "Full House", 'H');
+        rules
+    }
+
+    /// The same rules with rows filled top to bottom.
+    pub fn forced(&self) -> Self {
+        let mut rules = self.clone();
+        rules.name = format!("{}-forced", self.name);
+        rules.forced_order = true;
+        rules
+    }
+
+    /// Look up rules by name. A "-forced" suffix gives the forced order
+    /// variant, e.g. "yatzy-forced".
+    pub fn by_name(name: &str) -> Option<Self> {
+        if let Some(base) = name.strip_suffix("-forced") {
+            return RuleSet::by_name(base).filter(|r| !r.forced_order).map(|r| r.forced());
+        }
+        match name {
+            "super-yahtzee" => Some(RuleSet::super_yahtzee()),
+            "maxi-yatzy" => Some(RuleSet::maxi_yatzy()),
+            "yahtzee" => Some(RuleSet::yahtzee()),
+            "yatzy" => Some(RuleSet::yatzy()),
+            _ => None,
+        }
+    }
+
+    pub fn set_comb_label(&mut self, comb: Comb, name: &str, symbol: char) {
+        self.comb_names[comb] = name.to_owned();
+        self.comb_symbols[comb] = symbol;
+    }
+
+    pub fn comb_name(&self, comb: Comb) -> &str {
+        &self.comb_names[comb]
+    }
+
+    pub fn comb_symbol(&self, comb: Comb) -> char {
+        self.comb_symbols[comb]
+    }
+
+    /// Default file name of the state value table for these rules.
+    pub fn table_path(&self) -> String {
+        if *self == RuleSet::super_yahtzee() {
+            "state_value.bin".to_owned()
+        } else {
+            format!("state_value_{}.bin", self.name)
+        }
+    }
+
+    /// Default file name of the distribution table for these rules,
+    /// see `DistributionStore`.
+    pub fn distribution_path(&self) -> String {
+        self.table_path().replacen("state_value", "distribution", 1)
+    }
+
+    /// Default file name of the target value table for these rules,
+    /// see `TargetStore`.
+    pub fn target_path(&self) -> String {
+        self.table_path().replacen("state_value", "target_value", 1)
+    }
+
+    /// Points to subtract from the total when the upper section
+    /// is written relative to `bonus_count` of each side.
+    pub fn upper_par(&self) -> u32 {
+        if self.relative_upper { self.bonus_limit } else { 0 }
+    }
+
+    pub fn combs(&self) -> &[Comb] {
+        &self.combs
+    }
+
+    pub fn comb_count(&self) -> usize {
+        self.combs.len()
+    }
+
+    /// Mask of the rows in this variant, indexed by `Comb`.
+    pub fn comb_mask(&self) -> u32 {
+        self.comb_mask
+    }
+
+    pub fn has_comb(&self, comb: Comb) -> bool {
+        self.comb_mask & (1 << comb) != 0
+    }
+
+    /// Position of the row on the score sheet.
+    pub fn comb_index(&self, comb: Comb) -> Option<usize> {
+        self.combs.iter().position(|&c| c == comb)
+    }
+
+    /// Number of turns in a game, one for each row.
+    pub fn turns(&self) -> u32 {
+        (SIDES + self.comb_count()) as u32
+    }
+
+    pub fn state_count(&self) -> usize {
+        let flags = if self.yahtzee_bonus > 0 { 1 } else { 0 };
+        let rows = if self.forced_order {
+            self.turns() as usize + 1
+        } else {
+            1 << (SIDES + self.comb_count())
+        };
+        (((1 + self.bonus_limit as usize) * rows) << flags) * (self.max_saved_rerolls as usize + 1)
+    }
+
+    pub fn encode_combs(&self, combination_mask: u32) -> u32 {
+        if self.combs_in_order {
+            return combination_mask;
+        }
+        let mut r = 0;
+        for (i, &c) in self.combs.iter().enumerate() {
+            r |= ((combination_mask >> c) & 1) << i;
+        }
+        r
+    }
+
+    pub fn decode_combs(&self, v: u32) -> u32 {
+        if self.combs_in_order {
+            return v;
+        }
+        let mut r = 0;
+        for (i, &c) in self.combs.iter().enumerate() {
+            r |= ((v >> i) & 1) << c;
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn super_yahtzee_state_count() {
+        assert_eq!(RuleSet::super_yahtzee().state_count(), 22282240);
+    }
+}
@@ -1,9 +1,10 @@
 use std::fmt;
 use crate::constants::*;
 use crate::outcome::*;
+use crate::rules::RuleSet;
 use crate::state::*;
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum Action {
     Combination(Comb),
     Side(usize),
@@ -11,54 +12,96 @@ pub enum Action {
 
 impl fmt::Debug for Action {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            &Action::Combination(S2) => write!(fmt, "Action::Combination(S2)"),
-            &Action::Combination(S22) => write!(fmt, "Action::Combination(S22)"),
-            &Action::Combination(S222) => write!(fmt, "Action::Combination(S222)"),
-            &Action::Combination(S3) => write!(fmt, "Action::Combination(S3)"),
-            &Action::Combination(S4) => write!(fmt, "Action::Combination(S4)"),
-            &Action::Combination(S33) => write!(fmt, "Action::Combination(S33)"),
-            &Action::Combination(R15) => write!(fmt, "Action::Combination(R15)"),
-            &Action::Combination(R26) => write!(fmt, "Action::Combination(R26)"),
-            &Action::Combination(R16) => write!(fmt, "Action::Combination(R16)"),
-            &Action::Combination(S23) => write!(fmt, "Action::Combination(S23)"),
-            &Action::Combination(CHANCE) => write!(fmt, "Action::Combination(CHANCE)"),
-            &Action::Combination(YAHTZEE) => write!(fmt, "Action::Combination(YAHTZEE)"),
-            &Action::Combination(c) => write!(fmt, "Action::Combination({})", c),
-            &Action::Side(s) => write!(fmt, "Action::Side({} - 1)", s + 1),
+        match *self {
+            Action::Combination(S2) => write!(fmt, "Action::Combination(S2)"),
+            Action::Combination(S22) => write!(fmt, "Action::Combination(S22)"),
+            Action::Combination(S222) => write!(fmt, "Action::Combination(S222)"),
+            Action::Combination(S3) => write!(fmt, "Action::Combination(S3)"),
+            Action::Combination(S4) => write!(fmt, "Action::Combination(S4)"),
+            Action::Combination(S33) => write!(fmt, "Action::Combination(S33)"),
+            Action::Combination(R15) => write!(fmt, "Action::Combination(R15)"),
+            Action::Combination(R26) => write!(fmt, "Action::Combination(R26)"),
+            Action::Combination(R16) => write!(fmt, "Action::Combination(R16)"),
+            Action::Combination(S23) => write!(fmt, "Action::Combination(S23)"),
+            Action::Combination(CHANCE) => write!(fmt, "Action::Combination(CHANCE)"),
+            Action::Combination(YAHTZEE) => write!(fmt, "Action::Combination(YAHTZEE)"),
+            Action::Combination(K3) => write!(fmt, "Action::Combination(K3)"),
+            Action::Combination(K4) => write!(fmt, "Action::Combination(K4)"),
+            Action::Combination(FULL_HOUSE) => write!(fmt, "Action::Combination(FULL_HOUSE)"),
+            Action::Combination(SM_STRAIGHT) => write!(fmt, "Action::Combination(SM_STRAIGHT)"),
+            Action::Combination(LG_STRAIGHT) => write!(fmt, "Action::Combination(LG_STRAIGHT)"),
+            Action::Combination(YAHTZEE50) => write!(fmt, "Action::Combination(YAHTZEE50)"),
+            Action::Combination(YATZY) => write!(fmt, "Action::Combination(YATZY)"),
+            Action::Combination(c) => write!(fmt, "Action::Combination({})", c),
+            Action::Side(s) => write!(fmt, "Action::Side({} - 1)", s + 1),
         }
     }
 }
 
 impl Action {
-    pub fn shorthand(&self) -> String {
-        let symbols = b"PDTVQWsSCH?!";
-        match self {
-            &Action::Combination(n) => format!("{}", symbols[n] as char),
-            &Action::Side(s) => format!("{}", s + 1),
+    pub fn shorthand(&self, rules: &RuleSet) -> String {
+        match *self {
+            Action::Combination(n) => format!("{}", rules.comb_symbol(n)),
+            Action::Side(s) => format!("{}", s + 1),
         }
     }
 
-    pub fn name(&self) -> String {
-        match self {
-            &Action::Combination(S2) => format!("Pair"),
-            &Action::Combination(S22) => format!("Two Pairs"),
-            &Action::Combination(S222) => format!("Three Pairs"),
-            &Action::Combination(S3) => format!("Three of a Kind"),
-            &Action::Combination(S4) => format!("Four of a Kind"),
-            &Action::Combination(S33) => format!("2xThree of a Kind"),
-            &Action::Combination(R15) => format!("Low Straight"),
-            &Action::Combination(R26) => format!("High Straight"),
-            &Action::Combination(R16) => format!("Cameron"),
-            &Action::Combination(S23) => format!("House"),
-            &Action::Combination(CHANCE) => format!("Chance"),
-            &Action::Combination(YAHTZEE) => format!("Yahtzee"),
-            &Action::Combination(c) => format!("Unknown {}", c),
-            &Action::Side(s) => format!("{}'s", s + 1),
+    pub fn name(&self, rules: &RuleSet) -> String {
+        match *self {
+            Action::Combination(c) => rules.comb_name(c).to_owned(),
+            Action::Side(s) => format!("{}'s", s + 1),
+        }
+    }
+
+    /// Position of the row on the score sheet of the given rules:
+    /// combination rows first, then the sides.
+    pub fn index(&self, rules: &RuleSet) -> usize {
+        match *self {
+            Action::Combination(c) => rules.comb_index(c).expect("Combination not in rules"),
+            Action::Side(s) => s + rules.comb_count(),
+        }
+    }
+
+    /// The row at the given position on the score sheet, see `index`.
+    pub fn from_index(rules: &RuleSet, index: usize) -> Option<Action> {
+        match rules.combs().get(index) {
+            Some(&c) => Some(Action::Combination(c)),
+            None if index < rules.comb_count() + SIDES => Some(Action::Side(index - rules.comb_count())),
+            None => None,
         }
     }
 }
 
+pub(crate) fn default_comb_name(comb: Comb) -> &'static str {
+    match comb {
+        S2 => "Pair",
+        S22 => "Two Pairs",
+        S222 => "Three Pairs",
+        S3 => "Three of a Kind",
+        S4 => "Four of a Kind",
+        S33 => "2xThree of a Kind",
+        R15 => "Low Straight",
+        R26 => "High Straight",
+        R16 => "Cameron",
+        S23 => "House",
+        CHANCE => "Chance",
+        YAHTZEE => "Yahtzee",
+        K3 => "3 of a Kind",
+        K4 => "4 of a Kind",
+        FULL_HOUSE => "Full House",
+        SM_STRAIGHT => "Sm Straight",
+        LG_STRAIGHT => "Lg Straight",
+        YAHTZEE50 => "Yahtzee",
+        YATZY => "Yatzy",
+        _ => "Unknown",
+    }
+}
+
+pub(crate) fn default_comb_symbol(comb: Comb) -> char {
+    let symbols = b"PDTVQWsSCH?!TFHsS!!";
+    symbols[comb] as char
+}
+
 fn score_pairs<F: FnMut(Comb, u32)>(o: Outcome, f: &mut F) {
     let mut pair_sum = 0u32;
     let mut pairs = 0;
@@ -70,8 +113,8 @@ fn score_pairs<F: FnMut(Comb, u32)>(o: Outcome, f: &mut F) {
             pairs += 1;
         }
     }
-    for i in pairs..pair_scores.len() {
-        f(pair_scores[i], 0);
+    for &comb in pair_scores.iter().skip(pairs) {
+        f(comb, 0);
     }
 }
 
@@ -83,14 +126,15 @@ fn score_sum<F: FnMut(Comb, u32)>(o: Outcome, f: &mut F) {
     f(CHANCE, sum);
 }
 
-fn score_yahtzee<F: FnMut(Comb, u32)>(o: Outcome, f: &mut F) {
+fn score_yahtzee<F: FnMut(Comb, u32)>(rules: &RuleSet, o: Outcome, f: &mut F) {
     let mut s6 = 0;
     for d in (0..SIDES).rev() {
-        if o.histogram[d] == DICE_COUNT as u8 {
+        if o.histogram[d] == rules.dice_count as u8 {
             s6 = d as u32 + 1;
         }
     }
-    f(YAHTZEE, if s6 > 0 { 100 + DICE_COUNT as u32 * s6 } else { 0 });
+    f(YAHTZEE, if s6 > 0 { 100 + rules.dice_count as u32 * s6 } else { 0 });
+    f(YATZY, if s6 > 0 { 50 } else { 0 });
 }
 
 fn score_combinations<F: FnMut(Comb, u32)>(o: Outcome, f: &mut F) {
@@ -118,83 +162,220 @@ fn score_combinations<F: FnMut(Comb, u32)>(o: Outcome, f: &mut F) {
 }
 
 fn score_singles<F: FnMut(Comb, u32)>(o: Outcome, f: &mut F) {
-    let mut singles = 0;
-    for d in (0..SIDES).rev() {
-        if o.histogram[d] == 1 {
-            singles += 1;
-        }
+    let h = o.histogram;
+    let r25 = h[1] > 0 && h[2] > 0 && h[3] > 0 && h[4] > 0;
+    let r15 = r25 && h[0] > 0;
+    let r26 = r25 && h[5] > 0;
+    f(R15, if r15 { 15 } else { 0 });
+    f(R26, if r26 { 20 } else { 0 });
+    f(R16, if r15 && r26 { 30 } else { 0 });
+}
+
+fn longest_run(o: Outcome) -> usize {
+    let mut run = 0;
+    let mut longest = 0;
+    for &c in o.histogram.iter() {
+        run = if c > 0 { run + 1 } else { 0 };
+        longest = longest.max(run);
     }
-    if singles == 6 {
-        f(R15, 15);
-        f(R26, 20);
-        f(R16, 30);
-    } else if singles == 4 && o.histogram[0] == 0 {
-        f(R15, 0);
-        f(R26, 20);
-        f(R16, 0);
-    } else if singles == 4 && o.histogram[5] == 0 {
-        f(R15, 15);
-        f(R26, 0);
-        f(R16, 0);
-    } else {
-        f(R15, 0);
-        f(R26, 0);
-        f(R16, 0);
+    longest
+}
+
+fn score_classic<F: FnMut(Comb, u32)>(rules: &RuleSet, o: Outcome, f: &mut F) {
+    let mut sum = 0;
+    let mut most = 0;
+    let mut pair = false;
+    for d in 0..SIDES {
+        sum += (d as u32 + 1) * (o.histogram[d] as u32);
+        most = most.max(o.histogram[d]);
+        pair = pair || o.histogram[d] == 2;
+    }
+    let run = longest_run(o);
+    f(K3, if most >= 3 { sum } else { 0 });
+    f(K4, if most >= 4 { sum } else { 0 });
+    f(FULL_HOUSE, if most == 3 && pair { 25 } else { 0 });
+    f(SM_STRAIGHT, if run >= 4 { 30 } else { 0 });
+    f(LG_STRAIGHT, if run >= 5 { 40 } else { 0 });
+    f(YAHTZEE50, if most as usize == rules.dice_count { 50 } else { 0 });
+}
+
+/// Points for a Yahtzee played as a Joker in a combination row.
+fn joker_score(comb: Comb, o: Outcome) -> u32 {
+    match comb {
+        FULL_HOUSE => 25,
+        SM_STRAIGHT => 30,
+        LG_STRAIGHT => 40,
+        _ => {
+            let mut sum = 0;
+            for d in 0..SIDES {
+                sum += (d as u32 + 1) * (o.histogram[d] as u32);
+            }
+            sum
+        },
     }
 }
 
-fn possible_scores<F: FnMut(Comb, u32)>(o: Outcome, s: State, mut f: F) {
+fn possible_scores<F: FnMut(Comb, u32)>(rules: &RuleSet, o: Outcome, s: State, mut f: F) {
+    let open = rules.comb_mask() & !s.combination_mask;
     let pairs = (1 << S2) | (1 << S22) | (1 << S222);
-    if s.combination_mask & pairs != pairs { score_pairs(o, &mut f); }
-    if !s.has_comb(CHANCE) { score_sum(o, &mut f); }
-    if !s.has_comb(YAHTZEE) { score_yahtzee(o, &mut f); }
+    if open & pairs != 0 { score_pairs(o, &mut f); }
+    if open & (1 << CHANCE) != 0 { score_sum(o, &mut f); }
+    if open & ((1 << YAHTZEE) | (1 << YATZY)) != 0 { score_yahtzee(rules, o, &mut f); }
     let combs = (1 << S3) | (1 << S4) | (1 << S33) | (1 << S23);
-    if s.combination_mask & combs != combs { score_combinations(o, &mut f); }
+    if open & combs != 0 { score_combinations(o, &mut f); }
     let singles = (1 << R15) | (1 << R26) | (1 << R16);
-    if s.combination_mask & singles != singles { score_singles(o, &mut f); }
+    if open & singles != 0 { score_singles(o, &mut f); }
+    let classic = (1 << K3) | (1 << K4) | (1 << FULL_HOUSE) | (1 << SM_STRAIGHT) | (1 << LG_STRAIGHT) | (1 << YAHTZEE50);
+    if open & classic != 0 { score_classic(rules, o, &mut f); }
+}
+
+/// Points of the roll `o` in every row of the rules as `f(comb, points)`,
+/// not counting Jokers.
+pub fn comb_scores<F: FnMut(Comb, u32)>(rules: &RuleSet, o: Outcome, mut f: F) {
+    let mask = rules.comb_mask();
+    possible_scores(rules, o, State::initial(), |comb, s| {
+        if mask & (1 << comb) != 0 {
+            f(comb, s);
+        }
+    });
+}
+
+fn side_action(rules: &RuleSet, state: State, o: Outcome, d: usize) -> (State, u32) {
+    let score = state.score;
+    let bonus_limit = rules.bonus_limit;
+    let s = (d as u32 + 1) * (o.histogram[d] as u32);
+    let (new_score, bonus) =
+        if score < bonus_limit && score + s >= bonus_limit {
+            (bonus_limit, rules.bonus)
+        } else if score < bonus_limit {
+            (score + s, 0)
+        } else {
+            (score, 0)
+        };
+    (state.with_side(d).with_score(new_score), s + bonus)
+}
+
+/// Actions for a Yahtzee of the given side rolled after the Yahtzee row
+/// is filled, following the forced Joker rule.
+fn joker_actions<F: FnMut(Action, State, u32)>(rules: &RuleSet, state: State, o: Outcome, side: usize, f: &mut F) {
+    let bonus = if state.yahtzee_bonus { rules.yahtzee_bonus } else { 0 };
+    if !state.has_side(side) {
+        let (next_state, points) = side_action(rules, state, o, side);
+        f(Action::Side(side), next_state, points + bonus);
+        return;
+    }
+    let open = rules.comb_mask() & !state.combination_mask;
+    if open != 0 {
+        for &comb in rules.combs() {
+            if open & (1 << comb) != 0 {
+                f(Action::Combination(comb), state.with_comb(comb), joker_score(comb, o) + bonus);
+            }
+        }
+        return;
+    }
+    for d in 0..SIDES {
+        if !state.has_side(d) {
+            let (next_state, points) = side_action(rules, state, o, d);
+            f(Action::Side(d), next_state, points + bonus);
+        }
+    }
 }
 
-// state:32 is score:7 sides:6 combinations:12
+// For Super Yahtzee, state:32 is score:7 sides:6 combinations:12
 // score is in 0..85, so number of states is 85*2**18 = 22282240
 // f(action, next_state, points)
-pub fn actions<F: FnMut(Action, State, u32)>(state: State, o: Outcome, mut f: F) {
-    let score = state.score;
+// Rerolls saved in next_state are left for the caller to fill in.
+pub fn actions<F: FnMut(Action, State, u32)>(rules: &RuleSet, state: State, o: Outcome, mut f: F) {
+    let state = state.with_saved_rerolls(0);
+    if rules.forced_order {
+        forced_action(rules, state, o, &mut f);
+        return;
+    }
+    if rules.joker && state.has_comb(YAHTZEE50) {
+        if let Some(side) = o.histogram.iter().position(|&c| c as usize == rules.dice_count) {
+            joker_actions(rules, state, o, side, &mut f);
+            return;
+        }
+    }
     for d in 0..SIDES {
         if state.has_side(d) {
             continue;
         }
-        let s = (d as u32 + 1) * (o.histogram[d] as u32);
-        let (new_score, bonus) =
-            if score < BONUS_LIMIT && score + s >= BONUS_LIMIT {
-                (BONUS_LIMIT, BONUS)
-            } else if score < BONUS_LIMIT {
-                (score + s, 0)
-            } else {
-                (score, 0)
-            };
-        f(Action::Side(d), state.with_side(d).with_score(new_score), s + bonus);
-    }
-    possible_scores(o, state, |comb, s| {
-        if state.has_comb(comb) {
-            return;
+        let (next_state, points) = side_action(rules, state, o, d);
+        f(Action::Side(d), next_state, points);
+    }
+    let open = rules.comb_mask() & !state.combination_mask;
+    possible_scores(rules, o, state, |comb, s| {
+        if open & (1 << comb) != 0 {
+            f(Action::Combination(comb), comb_next_state(rules, state, comb, s), s);
         }
-        f(Action::Combination(comb), state.with_comb(comb), s);
     });
 }
 
+fn comb_next_state(rules: &RuleSet, state: State, comb: Comb, points: u32) -> State {
+    let next_state = state.with_comb(comb);
+    if comb == YAHTZEE50 && points > 0 && rules.yahtzee_bonus > 0 {
+        next_state.with_yahtzee_bonus()
+    } else {
+        next_state
+    }
+}
+
+/// The next row to fill when rows are filled top to bottom.
+pub fn next_row(rules: &RuleSet, state: State) -> Option<Action> {
+    if let Some(d) = (0..SIDES).find(|&d| !state.has_side(d)) {
+        return Some(Action::Side(d));
+    }
+    rules.combs().iter().find(|&&c| !state.has_comb(c)).map(|&c| Action::Combination(c))
+}
+
+/// The only action in forced order: putting the roll on the next row.
+/// A Joker scores full points there.
+fn forced_action<F: FnMut(Action, State, u32)>(rules: &RuleSet, state: State, o: Outcome, f: &mut F) {
+    let yahtzee = o.histogram.iter().any(|&c| c as usize == rules.dice_count);
+    let joker = rules.joker && yahtzee && state.has_comb(YAHTZEE50);
+    let bonus = if yahtzee && state.yahtzee_bonus { rules.yahtzee_bonus } else { 0 };
+    match next_row(rules, state) {
+        None => (),
+        Some(Action::Side(d)) => {
+            let (next_state, points) = side_action(rules, state, o, d);
+            f(Action::Side(d), next_state, points + bonus);
+        },
+        Some(Action::Combination(comb)) if joker => {
+            f(Action::Combination(comb), state.with_comb(comb), joker_score(comb, o) + bonus);
+        },
+        Some(Action::Combination(comb)) => {
+            let mut points = 0;
+            possible_scores(rules, o, state, |c, s| if c == comb { points = s; });
+            f(Action::Combination(comb), comb_next_state(rules, state, comb, points), points + bonus);
+        },
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
     use crate::constants::*;
+    #[test]
+    fn action_index() {
+        // Rows by score sheet position, as in the C API.
+        let rules = RuleSet::yatzy();
+        for i in 0..rules.comb_count() + SIDES {
+            assert_eq!(Action::from_index(&rules, i).unwrap().index(&rules), i);
+        }
+        assert_eq!(Action::from_index(&rules, rules.comb_count() + SIDES), None);
+    }
+
     #[test]
     fn test_actions() {
-        let s = State { combination_mask: 0x08ff, sides_mask: 0x01, score: 0 };
+        let rules = RuleSet::super_yahtzee();
+        let s = State { combination_mask: 0x08ff, sides_mask: 0x01, score: 0, yahtzee_bonus: false, saved_rerolls: 0 };
         let o = Outcome { histogram: [0, 2, 0, 0, 3, 1] };
         // 28.085730574395857   000018ff 0 1-----  -4 PDTVQWsS---!
         let mut acts = Vec::new();
         let mut states = Vec::new();
         let mut pts = Vec::new();
-        actions(s, o, |action, next_state, points| {
+        actions(&rules, s, o, |action, next_state, points| {
             acts.push(action);
             states.push(next_state);
             pts.push(points);
@@ -210,15 +391,92 @@ mod tests {
             Action::Combination(R16),
         ]);
         assert_eq!(states, vec![
-            State { combination_mask: 0x08ff, sides_mask: 0x03, score: 4 },
-            State { combination_mask: 0x08ff, sides_mask: 0x05, score: 0 },
-            State { combination_mask: 0x08ff, sides_mask: 0x09, score: 0 },
-            State { combination_mask: 0x08ff, sides_mask: 0x11, score: 15 },
-            State { combination_mask: 0x08ff, sides_mask: 0x21, score: 6 },
-            State { combination_mask: 0x0cff, sides_mask: 0x01, score: 0 },
-            State { combination_mask: 0x0aff, sides_mask: 0x01, score: 0 },
-            State { combination_mask: 0x09ff, sides_mask: 0x01, score: 0 },
+            State { combination_mask: 0x08ff, sides_mask: 0x03, score: 4, yahtzee_bonus: false, saved_rerolls: 0 },
+            State { combination_mask: 0x08ff, sides_mask: 0x05, score: 0, yahtzee_bonus: false, saved_rerolls: 0 },
+            State { combination_mask: 0x08ff, sides_mask: 0x09, score: 0, yahtzee_bonus: false, saved_rerolls: 0 },
+            State { combination_mask: 0x08ff, sides_mask: 0x11, score: 15, yahtzee_bonus: false, saved_rerolls: 0 },
+            State { combination_mask: 0x08ff, sides_mask: 0x21, score: 6, yahtzee_bonus: false, saved_rerolls: 0 },
+            State { combination_mask: 0x0cff, sides_mask: 0x01, score: 0, yahtzee_bonus: false, saved_rerolls: 0 },
+            State { combination_mask: 0x0aff, sides_mask: 0x01, score: 0, yahtzee_bonus: false, saved_rerolls: 0 },
+            State { combination_mask: 0x09ff, sides_mask: 0x01, score: 0, yahtzee_bonus: false, saved_rerolls: 0 },
         ]);
         assert_eq!(pts, vec![4, 0, 0, 15, 6, 25, 19, 0]);
     }
+
+    fn collect(rules: &RuleSet, s: State, o: Outcome) -> Vec<(Action, State, u32)> {
+        let mut res = Vec::new();
+        actions(rules, s, o, |action, next_state, points| res.push((action, next_state, points)));
+        res
+    }
+
+    #[test]
+    fn test_yahtzee_actions() {
+        let rules = RuleSet::yahtzee();
+        let o = Outcome { histogram: [0, 0, 3, 0, 2, 0] };
+        let pts: Vec<_> = collect(&rules, State::initial(), o).into_iter().map(|(_, _, p)| p).collect();
+        // 1-6, Chance, 3K, 4K, FH, SS, LS, Yahtzee
+        assert_eq!(pts, vec![0, 0, 9, 0, 10, 0, 19, 19, 0, 25, 0, 0, 0]);
+        let o = Outcome { histogram: [0, 1, 1, 1, 1, 1] };
+        let pts: Vec<_> = collect(&rules, State::all_sides(), o).into_iter().map(|(_, _, p)| p).collect();
+        assert_eq!(pts, vec![20, 0, 0, 0, 30, 40, 0]);
+    }
+
+    #[test]
+    fn test_forced_actions() {
+        let rules = RuleSet::yatzy().forced();
+        let o = Outcome { histogram: [0, 0, 3, 0, 2, 0] };
+        let s = State::initial().with_side(0).with_score(2);
+        assert_eq!(collect(&rules, s, o), vec![(Action::Side(1), State::initial().with_side(0).with_side(1).with_score(2), 0)]);
+        let s = State::all_sides().with_comb(S2);
+        assert_eq!(collect(&rules, s, o), vec![(Action::Combination(S22), s.with_comb(S22), 16)]);
+        let s = State::all_sides().with_comb(S2).with_comb(S22);
+        assert_eq!(next_row(&rules, s), Some(Action::Combination(S3)));
+    }
+
+    #[test]
+    fn test_yatzy_actions() {
+        let rules = RuleSet::yatzy();
+        let points = |o: Outcome| {
+            let mut res = Vec::new();
+            actions(&rules, State::all_sides(), o, |action, _, p| res.push((action.shorthand(&rules), p)));
+            res.sort();
+            res
+        };
+        let o = Outcome { histogram: [0, 0, 3, 0, 2, 0] };
+        // Full house and pairs score the dice used
+        assert_eq!(points(o), vec![
+            ("!".to_owned(), 0), ("?".to_owned(), 19), ("D".to_owned(), 16), ("H".to_owned(), 19),
+            ("P".to_owned(), 10), ("Q".to_owned(), 0), ("S".to_owned(), 0), ("V".to_owned(), 9),
+            ("s".to_owned(), 0)]);
+        let o = Outcome { histogram: [1, 1, 1, 1, 1, 0] };
+        assert_eq!(points(o).into_iter().filter(|&(_, p)| p > 0).collect::<Vec<_>>(),
+                   vec![("?".to_owned(), 15), ("s".to_owned(), 15)]);
+        let o = Outcome { histogram: [0, 0, 0, 0, 0, 5] };
+        assert_eq!(points(o).into_iter().filter(|&(_, p)| p > 0).collect::<Vec<_>>(),
+                   vec![("!".to_owned(), 50), ("?".to_owned(), 30), ("P".to_owned(), 12),
+                        ("Q".to_owned(), 24), ("V".to_owned(), 18)]);
+        assert_eq!(Action::Combination(S23).name(&rules), "Full House");
+    }
+
+    #[test]
+    fn test_yahtzee_joker() {
+        let rules = RuleSet::yahtzee();
+        let o = Outcome { histogram: [0, 0, 0, 5, 0, 0] };
+        let first = collect(&rules, State::initial(), o);
+        let (_, s, p) = first.iter().find(|(a, _, _)| *a == Action::Combination(YAHTZEE50)).unwrap();
+        assert_eq!(*p, 50);
+        assert!(s.yahtzee_bonus);
+        // Forced to take the 4's
+        let acts = collect(&rules, *s, o);
+        assert_eq!(acts.len(), 1);
+        assert_eq!(acts[0].0, Action::Side(4 - 1));
+        assert_eq!(acts[0].2, 120);
+        // Otherwise full points in any combination row
+        let acts = collect(&rules, s.with_side(4 - 1), o);
+        assert_eq!(acts.iter().map(|&(_, _, p)| p).collect::<Vec<_>>(), vec![120, 120, 125, 130, 140, 120]);
+        // Scratched Yahtzee row gives no bonus
+        let scratched = State::all_sides().with_comb(YAHTZEE50);
+        let acts = collect(&rules, scratched, o);
+        assert_eq!(acts.iter().map(|&(_, _, p)| p).collect::<Vec<_>>(), vec![20, 20, 25, 30, 40, 20]);
+    }
 }
@@ -1,30 +1,72 @@
 use std::fmt;
 use crate::constants::*;
+use crate::rules::RuleSet;
 
 #[derive(Clone, Copy, PartialEq)]
 pub struct State {
-    pub combination_mask: u16,
+    pub combination_mask: u32,
     pub sides_mask: u8,
     pub score: u32,
+    /// The Yahtzee row was filled with a Yahtzee,
+    /// so further Yahtzees earn `RuleSet::yahtzee_bonus`.
+    pub yahtzee_bonus: bool,
+    /// Rerolls left over from earlier turns, at most
+    /// `RuleSet::max_saved_rerolls`.
+    pub saved_rerolls: u32,
+}
+
+pub struct StateDisplay<'a> {
+    state: State,
+    rules: &'a RuleSet,
 }
 
 impl State {
-    pub fn decode(s: u32) -> Self {
-        let combination_mask = s as u16 & COMB_MASK;
-        let sides_mask = (s >> COMB_COUNT) as u8 & SIDES_MASK;
-        let score = s >> (COMB_COUNT + SIDES);
-        debug_assert!(score <= BONUS_LIMIT);
+    pub fn decode(rules: &RuleSet, mut s: u32) -> Self {
+        let saved_rerolls = s % (rules.max_saved_rerolls + 1);
+        s /= rules.max_saved_rerolls + 1;
+        let mut yahtzee_bonus = false;
+        if rules.yahtzee_bonus > 0 {
+            yahtzee_bonus = s & 1 != 0;
+            s >>= 1;
+        }
+        let comb_count = rules.comb_count();
+        let (combination_mask, sides_mask, score);
+        if rules.forced_order {
+            let turn = s % (rules.turns() + 1);
+            let sides = turn.min(SIDES as u32);
+            sides_mask = ((1 << sides) - 1) as u8;
+            combination_mask = rules.decode_combs((1 << (turn - sides)) - 1);
+            score = s / (rules.turns() + 1);
+        } else {
+            combination_mask = rules.decode_combs(s & ((1 << comb_count) - 1));
+            sides_mask = (s >> comb_count) as u8 & SIDES_MASK;
+            score = s >> (comb_count + SIDES);
+        }
+        debug_assert!(score <= rules.bonus_limit);
         State {
-            combination_mask: combination_mask,
-            sides_mask: sides_mask,
-            score: score,
+            combination_mask,
+            sides_mask,
+            score,
+            yahtzee_bonus,
+            saved_rerolls,
         }
     }
 
-    pub fn encode(&self) -> u32 {
-        (self.combination_mask as u32)
-            | ((self.sides_mask as u32) << COMB_COUNT)
-            | (self.score << (COMB_COUNT + SIDES))
+    pub fn encode(&self, rules: &RuleSet) -> u32 {
+        let comb_count = rules.comb_count();
+        let mut s = if rules.forced_order {
+            // Rows are filled in order, so the turn determines them.
+            self.score * (rules.turns() + 1) + self.turn_count()
+        } else {
+            rules.encode_combs(self.combination_mask)
+                | ((self.sides_mask as u32) << comb_count)
+                | (self.score << (comb_count + SIDES))
+        };
+        if rules.yahtzee_bonus > 0 {
+            s = (s << 1) | self.yahtzee_bonus as u32;
+        }
+        debug_assert!(self.saved_rerolls <= rules.max_saved_rerolls);
+        s * (rules.max_saved_rerolls + 1) + self.saved_rerolls
     }
 
     pub fn turn_count(&self) -> u32 {
@@ -36,6 +78,8 @@ impl State {
             combination_mask: 0,
             sides_mask: 0,
             score: 0,
+            yahtzee_bonus: false,
+            saved_rerolls: 0,
         }
     }
 
@@ -44,11 +88,13 @@ impl State {
             combination_mask: 0,
             sides_mask: SIDES_MASK,
             score: 0,
+            yahtzee_bonus: false,
+            saved_rerolls: 0,
         }
     }
 
-    pub fn done(&self) -> bool {
-        self.combination_mask == COMB_MASK && self.sides_mask == SIDES_MASK
+    pub fn done(&self, rules: &RuleSet) -> bool {
+        self.combination_mask == rules.comb_mask() && self.sides_mask == SIDES_MASK
     }
 
     pub fn has_side(&self, side: usize) -> bool {
@@ -59,9 +105,8 @@ impl State {
     pub fn with_side(&self, side: usize) -> State {
         debug_assert!(side < SIDES);
         State {
-            combination_mask: self.combination_mask,
             sides_mask: self.sides_mask | (1 << side),
-            score: self.score,
+            ..*self
         }
     }
 
@@ -74,91 +119,169 @@ impl State {
         debug_assert!(comb < COMB_COUNT);
         State {
             combination_mask: self.combination_mask | (1 << comb),
-            sides_mask: self.sides_mask,
-            score: self.score,
+            ..*self
         }
     }
 
     pub fn with_score(&self, score: u32) -> State {
-        debug_assert!(score <= BONUS_LIMIT);
         State {
-            combination_mask: self.combination_mask,
-            sides_mask: self.sides_mask,
-            score: score,
+            score,
+            ..*self
+        }
+    }
+
+    pub fn with_yahtzee_bonus(&self) -> State {
+        State {
+            yahtzee_bonus: true,
+            ..*self
+        }
+    }
+
+    pub fn with_saved_rerolls(&self, saved_rerolls: u32) -> State {
+        State {
+            saved_rerolls,
+            ..*self
         }
     }
 
-    pub fn upper_bound_points(&self) -> u32 {
+    /// Rerolls available at the start of the turn.
+    pub fn rerolls(&self, rules: &RuleSet) -> usize {
+        rules.reroll_count + self.saved_rerolls as usize
+    }
+
+    pub fn upper_bound_points(&self, rules: &RuleSet) -> u32 {
+        let dice_count = rules.dice_count as u32;
         let mut ub = 0;
         let mut score = self.score;
         for d in 0..SIDES {
             if self.has_side(d) { continue; }
-            let s = (d as u32 + 1) * (DICE_COUNT as u32);
-            if score < BONUS_LIMIT && score + s >= BONUS_LIMIT {
-                score = BONUS_LIMIT;
-                ub += BONUS;
+            let s = (d as u32 + 1) * dice_count;
+            if score < rules.bonus_limit && score + s >= rules.bonus_limit {
+                score = rules.bonus_limit;
+                ub += rules.bonus;
             } else {
                 score += s;
             }
             ub += s;
         }
-        if !self.has_comb(S2) { ub += 2 * SIDES as u32; }
-        if !self.has_comb(S22) { ub += 4 * SIDES as u32 - 2; }
-        if !self.has_comb(S222) { ub += 6 * SIDES as u32 - 6; }
-        if !self.has_comb(S3) { ub += 3 * SIDES as u32; }
-        if !self.has_comb(S4) { ub += 4 * SIDES as u32; }
-        if !self.has_comb(S33) { ub += 6 * SIDES as u32 - 3; }
-        if !self.has_comb(R15) { ub += 1 + 2 + 3 + 4 + 5; }
-        if !self.has_comb(R26) { ub += 2 + 3 + 4 + 5 + 6; }
-        if !self.has_comb(R16) { ub += 30; }
-        if !self.has_comb(S23) { ub += 5 * SIDES as u32 - 2; }
-        if !self.has_comb(CHANCE) { ub += DICE_COUNT as u32 * SIDES as u32; }
-        if !self.has_comb(YAHTZEE) { ub += 100 + DICE_COUNT as u32 * SIDES as u32; }
+        for &c in rules.combs() {
+            if !self.has_comb(c) { ub += comb_upper_bound(rules, c); }
+        }
         // 42 * 6 - 13 + 15 + 20 + 30 + 100 + 126 + 50 = 580
+        if rules.yahtzee_bonus > 0 {
+            // Every remaining turn but the one filling the Yahtzee row
+            // may give a bonus.
+            let turns = rules.turns() - self.turn_count();
+            if self.yahtzee_bonus {
+                ub += rules.yahtzee_bonus * turns;
+            } else if !self.has_comb(YAHTZEE50) {
+                ub += rules.yahtzee_bonus * (turns - 1);
+            }
+        }
         ub
     }
 
-    pub fn display_score(&self, points: u32) -> i32 {
+    pub fn display_score(&self, rules: &RuleSet, points: u32) -> i32 {
         let mut points = points as i32;
+        if !rules.relative_upper {
+            return points;
+        }
         for d in 0..SIDES {
-            if self.has_side(d) { points -= BONUS_COUNT as i32 * (1 + d as i32); }
+            if self.has_side(d) { points -= rules.bonus_count as i32 * (1 + d as i32); }
         }
         points
     }
+
+    pub fn display<'a>(&self, rules: &'a RuleSet) -> StateDisplay<'a> {
+        StateDisplay {
+            state: *self,
+            rules,
+        }
+    }
+}
+
+fn comb_upper_bound(rules: &RuleSet, comb: Comb) -> u32 {
+    let sides = SIDES as u32;
+    let dice_count = rules.dice_count as u32;
+    match comb {
+        S2 => 2 * sides,
+        S22 => 4 * sides - 2,
+        S222 => 6 * sides - 6,
+        S3 => 3 * sides,
+        S4 => 4 * sides,
+        S33 => 6 * sides - 3,
+        R15 => 1 + 2 + 3 + 4 + 5,
+        R26 => 2 + 3 + 4 + 5 + 6,
+        R16 => 30,
+        S23 => 5 * sides - 2,
+        CHANCE => dice_count * sides,
+        YAHTZEE => 100 + dice_count * sides,
+        K3 | K4 => dice_count * sides,
+        FULL_HOUSE => 25,
+        SM_STRAIGHT => 30,
+        LG_STRAIGHT => 40,
+        YAHTZEE50 | YATZY => 50,
+        _ => unreachable!(),
+    }
 }
 
 impl fmt::Debug for State {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "State {{ combination_mask: 0x{:04x}, sides_mask: 0x{:02x}, score: {} }}",
-            self.combination_mask, self.sides_mask, self.score)
+        write!(f, "State {{ combination_mask: 0x{:04x}, sides_mask: 0x{:02x}, score: {}",
+            self.combination_mask, self.sides_mask, self.score)?;
+        if self.yahtzee_bonus {
+            write!(f, ", yahtzee_bonus: true")?;
+        }
+        if self.saved_rerolls > 0 {
+            write!(f, ", saved_rerolls: {}", self.saved_rerolls)?;
+        }
+        write!(f, " }}")
     }
 }
 
-impl fmt::Display for State {
+impl<'a> fmt::Display for StateDisplay<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut score = self.score as i32;
+        let state = &self.state;
+        let rules = self.rules;
+        let mut score = state.score as i32;
         let mut has_all = true;
         for d in 0..SIDES {
-            if self.has_side(d) {
+            if state.has_side(d) {
                 write!(f, "{}", d + 1)?;
-                score -= BONUS_COUNT as i32 * (d as i32 + 1);
+                if rules.relative_upper {
+                    score -= rules.bonus_count as i32 * (d as i32 + 1);
+                }
             } else {
                 write!(f, "-")?;
                 has_all = false;
             }
         }
-        if score >= 0 && has_all {
-            score = BONUS as i32;
+        if rules.relative_upper {
+            if score >= 0 && has_all {
+                score = rules.bonus as i32;
+            }
+            write!(f, " {:+3} ", score)?;
+        } else if state.score >= rules.bonus_limit {
+            // Sum and Bonus rows
+            write!(f, " {:2} {:2} ", score, rules.bonus)?;
+        } else if has_all {
+            write!(f, " {:2}  0 ", score)?;
+        } else {
+            write!(f, " {:2}  - ", score)?;
         }
-        write!(f, " {:+3} ", score)?;
-        let symbols = "PDTVQWsSCH?!";
-        for (i, c) in symbols.chars().enumerate() {
-            if self.has_comb(i) {
-                write!(f, "{}", c)?;
+        for &c in rules.combs() {
+            if state.has_comb(c) {
+                write!(f, "{}", rules.comb_symbol(c))?;
             } else {
                 write!(f, "-")?;
             }
         }
+        if state.yahtzee_bonus {
+            write!(f, "+")?;
+        }
+        if rules.max_saved_rerolls > 0 {
+            write!(f, " r{}", state.saved_rerolls)?;
+        }
         Ok(())
     }
 }
@@ -169,14 +292,67 @@ mod tests {
     use crate::constants::*;
     #[test]
     fn state_roundtrip() {
-        let states = (1 + BONUS_LIMIT as usize) << (SIDES + COMB_COUNT);
+        let rules = RuleSet::super_yahtzee();
+        let states = rules.state_count();
         assert!(states > 10);
         for i in 0..states {
-            let s = State::decode(i as u32);
-            let j = s.encode();
-            let r = State::decode(j);
+            let s = State::decode(&rules, i as u32);
+            let j = s.encode(&rules);
+            let r = State::decode(&rules, j);
             assert_eq!(s, r);
             assert_eq!(i, j as usize);
         }
     }
+
+    #[test]
+    fn yahtzee_roundtrip() {
+        let rules = RuleSet::yahtzee();
+        for i in 0..rules.state_count() {
+            let s = State::decode(&rules, i as u32);
+            assert_eq!(i, s.encode(&rules) as usize);
+        }
+        let s = State::initial().with_comb(YAHTZEE50).with_yahtzee_bonus();
+        assert_eq!(s, State::decode(&rules, s.encode(&rules)));
+    }
+
+    #[test]
+    fn saved_rerolls_roundtrip() {
+        let rules = RuleSet::maxi_yatzy();
+        assert_eq!(rules.state_count(), RuleSet::super_yahtzee().state_count() * 4);
+        for i in 0..1 << 16 {
+            let s = State::decode(&rules, i * 1000 + 7);
+            assert_eq!(s.encode(&rules), i * 1000 + 7);
+        }
+        let s = State::initial().with_comb(S2).with_saved_rerolls(3);
+        assert_eq!(s, State::decode(&rules, s.encode(&rules)));
+        // Spending saved rerolls still leads to a greater encoding
+        assert!(State::initial().with_saved_rerolls(3).encode(&rules) < State::initial().with_comb(S2).encode(&rules));
+    }
+
+    #[test]
+    fn forced_order_roundtrip() {
+        let rules = RuleSet::yatzy().forced();
+        assert_eq!(rules.state_count(), 64 * 16);
+        for i in 0..rules.state_count() {
+            let s = State::decode(&rules, i as u32);
+            assert_eq!(i, s.encode(&rules) as usize);
+        }
+        let s = State::all_sides().with_comb(S2).with_comb(S22).with_score(40);
+        assert_eq!(s, State::decode(&rules, s.encode(&rules)));
+    }
+
+    #[test]
+    fn yatzy_display() {
+        let rules = RuleSet::yatzy();
+        let s = State::initial().with_side(0).with_side(2).with_score(9).with_comb(S22).with_comb(YATZY);
+        assert_eq!(s.display(&rules).to_string(), "1-3---  9  - -D------!");
+        let s = State::all_sides().with_score(63).with_comb(S2);
+        assert_eq!(s.display(&rules).to_string(), "123456 63 50 P--------");
+    }
+
+    #[test]
+    fn yahtzee_initial_upper_bound() {
+        // 105 + 35 + 30 + 30 + 25 + 30 + 40 + 50 + 30 + 12 * 100
+        assert_eq!(State::initial().upper_bound_points(&RuleSet::yahtzee()), 1575);
+    }
 }
@@ -1,75 +1,303 @@
-extern crate byteorder;
 extern crate memmap;
 
 use std::fs;
-use byteorder::{ByteOrder, LittleEndian};
+use std::sync::Mutex;
 use crate::*;
-use crate::constants::*;
+use crate::compressed::CompressedTable;
+use crate::header::HEADER_SIZE;
+use crate::valuation::{expected_state_value, saved_rerolls_after};
+
+/// A row the roll can be put on, as ranked by `Store::rank_actions`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RankedAction {
+    pub action: Action,
+    pub points: u32,
+    pub next_state: State,
+    /// `points` plus the table value of `next_state`. For an expectation
+    /// table, that value is the expected points of the rest of the game
+    /// plus `RuleSet::upper_par`, so `total - upper_par` is the expected
+    /// points from this roll to the end of the game. NaN if `next_state`
+    /// is not in a reachable-only table.
+    pub total: f64,
+}
 
 pub struct Store {
     mmap: memmap::Mmap,
+    rules: RuleSet,
+    objective: Objective,
+    encoding: Encoding,
+    /// Bytes before the values, those of the `TableHeader` if any.
+    offset: usize,
+    /// Set for tables written by `write_compressed`.
+    compressed: Option<CompressedTable>,
+    /// Tables of the last state asked for, so the decisions of a turn
+    /// share one computation.
+    valuation: Mutex<Valuation>,
 }
 
 impl Store {
     pub fn new(path: &str) -> Result<Store> {
+        Store::with_rules(path, RuleSet::super_yahtzee())
+    }
+
+    pub fn with_rules(path: &str, rules: RuleSet) -> Result<Store> {
+        Store::with_objective(path, rules, Objective::Expectation)
+    }
+
+    /// Open a table computed for `objective` by `compute_objective_value`.
+    /// The `TableHeader` written by `write_table` is checked against the
+    /// rules and objective, giving `ErrorKind::RuleMismatch` if they
+    /// differ, `ErrorKind::Corrupt` if the size of the values does not
+    /// match it and `ErrorKind::UnsupportedVersion` for other versions of
+    /// the format. The checksum of the values is only checked by `verify`.
+    ///
+    /// Headerless tables from before the header was introduced are read
+    /// if their size fits an encoding, and give `ErrorKind::BadMagic`
+    /// otherwise.
+    ///
+    /// Tables written by `write_compressed` are also read, and checked
+    /// the same way. The values of the states left out are computed when
+    /// needed and kept in memory up to a fixed budget, giving the same
+    /// results as the full table. Only expectation tables can be
+    /// compressed.
+    pub fn with_objective(path: &str, rules: RuleSet, objective: Objective) -> Result<Store> {
         let file = fs::File::open(path)?;
         let mmap = unsafe { memmap::Mmap::map(&file)? };
+        let mut compressed = None;
+        let mut offset = 0;
+        let encoding = if let Some(header) = TableHeader::read(&mmap)? {
+            offset = HEADER_SIZE;
+            header.validate(&rules, objective, &mmap[offset..])?;
+            if header.compressed {
+                compressed = Some(CompressedTable::new(rules.state_count() as u32, &mmap[offset..]));
+            }
+            header.encoding
+        } else {
+            Encoding::detect(mmap.len(), rules.state_count()).ok_or(ErrorKind::BadMagic)?
+        };
         Ok(Store {
-            mmap: mmap,
+            mmap,
+            rules,
+            objective,
+            encoding,
+            offset,
+            compressed,
+            valuation: Mutex::new(Valuation::new()),
         })
     }
 
+    /// Check the values against the checksum in the header, giving
+    /// `ErrorKind::Corrupt` if they differ. This reads the whole table.
+    /// Tables without a header have no checksum and always pass.
+    pub fn verify(&self) -> Result<()> {
+        match TableHeader::read(&self.mmap)? {
+            Some(header) => header.verify(&self.mmap[self.offset..]),
+            None => Ok(()),
+        }
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    /// The objective the table was computed for.
+    pub fn objective(&self) -> Objective {
+        self.objective
+    }
+
+    /// How the values are stored.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
     pub fn len(&self) -> u32 {
-        let l = self.mmap.len() / 8;
-        assert!(l <= std::u32::MAX as usize);
+        if let Some(c) = &self.compressed {
+            return c.len();
+        }
+        let l = (self.mmap.len() - self.offset) / self.encoding.size();
+        assert!(l <= u32::MAX as usize);
         l as u32
     }
 
-    pub fn get(&self, s: u32) -> f64 {
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the expected score from the state, or the value for the
+    /// objective of the table if it is not `Objective::Expectation`.
+    /// Tables of only the reachable states store NaN for the other
+    /// states, giving `ErrorKind::Unreachable`.
+    pub fn get(&self, s: u32) -> Result<f64> {
+        let value = self.value(s);
+        if value.is_nan() {
+            return Err(ErrorKind::Unreachable.into());
+        }
+        Ok(value)
+    }
+
+    fn value(&self, s: u32) -> f64 {
         assert!(s < self.len());
-        let i = 8 * s as usize;
-        LittleEndian::read_f64(&self.mmap[i..i+8])
-    }
-
-    pub fn best_action(&self, state: u32, outcome: Outcome) -> Option<usize> {
-        let mut best = -1f64;
-        let mut best_action = None;
-        actions(State::decode(state), outcome, |action, next_state, points| {
-            let i = next_state.encode();
-            let value = self.get(i) + points as f64;
-            if value > best {
-                best = value;
-                best_action = Some(action);
-            }
+        if let Some(c) = &self.compressed {
+            let state = State::decode(&self.rules, s);
+            return c.value(&self.mmap[self.offset..], s, state.turn_count(), || {
+                expected_state_value(&self.rules, state, &mut |j| self.value(j), &mut Vec::new(), &mut Vec::new())
+            });
+        }
+        let i = self.offset + self.encoding.size() * s as usize;
+        self.encoding.read(&self.mmap[i..], &self.rules, s)
+    }
+
+    /// Returns the score sheet position of the best row when `rerolls`
+    /// rerolls remain in the turn, see `Action::index` and `rank_actions`.
+    pub fn best_action(&self, state: u32, outcome: Outcome, rerolls: usize) -> Option<usize> {
+        self.rank_actions(state, outcome, rerolls).first().map(|a| a.action.index(&self.rules))
+    }
+
+    /// Returns every row `outcome` can be put on in `state`, best first,
+    /// when `rerolls` rerolls remain in the turn. Under rules that save
+    /// rerolls, `next_state` has the rerolls saved by stopping. Rows of
+    /// equal value are in score sheet order, and rows with a NaN total
+    /// come last. Empty if the game is over.
+    ///
+    /// `rerolls` must be at most `State::rerolls` of the state.
+    pub fn rank_actions(&self, state: u32, outcome: Outcome, rerolls: usize) -> Vec<RankedAction> {
+        let rules = &self.rules;
+        let state = State::decode(rules, state);
+        assert!(rerolls <= state.rerolls(rules), "More rerolls than the state has");
+        let saved = saved_rerolls_after(rules, rerolls);
+        let mut ranked = Vec::new();
+        actions(rules, state, outcome, |action, next_state, points| {
+            let next_state = next_state.with_saved_rerolls(saved);
+            let total = self.value(next_state.encode(rules)) + points as f64;
+            ranked.push(RankedAction { action, points, next_state, total });
         });
-        let best_action = match best_action {
-            Some(a) => a,
-            None => return None,
-        };
-        match best_action {
-            Action::Combination(v) => Some(v),
-            Action::Side(s) => Some(s + COMB_COUNT),
+        ranked.sort_by(|a, b| a.total.is_nan().cmp(&b.total.is_nan()).then(b.total.total_cmp(&a.total)));
+        ranked
+    }
+
+    /// Returns the encoding of the dice to keep from `outcome` when
+    /// `rerolls` rerolls remain in the turn. Keeping every die means
+    /// the roll should be put on a row.
+    ///
+    /// `rerolls` must be at most `State::rerolls` of the state.
+    pub fn keep(&self, state: u32, outcome: Outcome, rerolls: usize) -> u32 {
+        let rules = &self.rules;
+        let state = State::decode(rules, state);
+        assert!(rerolls <= state.rerolls(rules), "More rerolls than the state has");
+        let mut valuation = self.valuation.lock().unwrap_or_else(|e| e.into_inner());
+        valuation.set_state(rules, state, &mut |i| self.value(i));
+        valuation.best_keep(rules, outcome, rerolls).encode()
+    }
+
+    pub fn keep_first(&self, state: u32, outcome: Outcome) -> u32 {
+        let rerolls = State::decode(&self.rules, state).rerolls(&self.rules);
+        self.keep(state, outcome, rerolls)
+    }
+
+    pub fn keep_second(&self, state: u32, outcome: Outcome) -> u32 {
+        let rerolls = State::decode(&self.rules, state).rerolls(&self.rules);
+        self.keep(state, outcome, rerolls - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testing::{self, TempFile};
+
+    #[test]
+    fn reachable_table() {
+        let rules = RuleSet::yahtzee().forced();
+        let value = testing::reachable_state_value(&rules);
+        let file = TempFile::new("reachable_test.bin");
+        std::fs::write(file.path(), value.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>()).unwrap();
+        let store = Store::with_rules(file.path(), rules.clone()).unwrap();
+        assert_eq!(store.get(0).unwrap(), value[0]);
+        let unreachable = value.iter().position(|v| v.is_nan()).unwrap();
+        assert!(matches!(store.get(unreachable as u32), Err(Error { kind: ErrorKind::Unreachable })));
+    }
+
+    /// The expected scores of the small rules written to `file` and
+    /// opened as a store.
+    fn small_store(file: &TempFile) -> Store {
+        let rules = testing::small_rules();
+        let value = testing::state_value(&rules);
+        write_table(file.path(), &rules, Objective::Expectation, Encoding::F64, &value).unwrap();
+        Store::with_rules(file.path(), rules).unwrap()
+    }
+
+    #[test]
+    fn rank_actions() {
+        let file = TempFile::new("rank_test.bin");
+        let store = small_store(&file);
+        let rules = store.rules().clone();
+        let value = testing::state_value(&rules);
+        for i in 0..rules.state_count() as u32 {
+            let state = State::decode(&rules, i);
+            for o in outcomes(&rules) {
+                let ranked = store.rank_actions(i, o, 0);
+                let mut count = 0;
+                let mut best = None;
+                actions(&rules, state, o, |action, next_state, points| {
+                    count += 1;
+                    let total = value[next_state.encode(&rules) as usize] + points as f64;
+                    if best.is_none_or(|(_, b)| total > b) {
+                        best = Some((action, total));
+                    }
+                });
+                assert_eq!(ranked.len(), count);
+                assert_eq!(ranked.first().map(|a| (a.action, a.total)), best);
+            }
         }
     }
 
-    pub fn keep_first(&self, state: u32, mut outcome: Outcome) -> u32 {
-        let state = State::decode(state);
-        let mut outcome_value = vec![0.0; max_outcome_encoding() + 1];
-        let mut reroll_value = vec![0.0; outcome_value.len()];
-        compute_outcome_values(state, &mut |i| self.get(i), &mut outcome_value);
-        compute_subset_expectations(&mut outcome_value);
-        compute_reroll_value(&outcome_value, &mut reroll_value);
-        compute_subset_expectations(&mut reroll_value);
-        choose_reroll(&mut outcome, &reroll_value);
-        outcome.encode()
+    #[test]
+    fn ranked_action_totals() {
+        let file = TempFile::new("rank_totals_test.bin");
+        let store = small_store(&file);
+        let rules = store.rules().clone();
+        for i in 0..rules.state_count() as u32 {
+            for o in outcomes(&rules) {
+                let ranked = store.rank_actions(i, o, 0);
+                for pair in ranked.windows(2) {
+                    assert!(pair[0].total >= pair[1].total);
+                }
+                for a in ranked.iter() {
+                    assert_eq!(a.total, store.get(a.next_state.encode(&rules)).unwrap() + a.points as f64);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rank_actions_saving_rerolls() {
+        let mut rules = testing::small_rules();
+        rules.max_saved_rerolls = 2;
+        let value = testing::state_value(&rules);
+        let file = TempFile::new("saved_rerolls_test.bin");
+        write_table(file.path(), &rules, Objective::Expectation, Encoding::F64, &value).unwrap();
+        let store = Store::with_rules(file.path(), rules.clone()).unwrap();
+        for i in (0..rules.state_count() as u32).step_by(7) {
+            let state = State::decode(&rules, i);
+            for rerolls in 0..(state.rerolls(&rules) + 1) {
+                let saved = (rerolls as u32).min(rules.max_saved_rerolls);
+                for o in outcomes(&rules) {
+                    let ranked = store.rank_actions(i, o, rerolls);
+                    assert!(ranked.iter().all(|a| a.next_state.saved_rerolls == saved));
+                    if let Some(best) = ranked.first() {
+                        assert_eq!(best.total, stop_value(&rules, state, o, saved, &mut |j| value[j as usize]));
+                    }
+                }
+            }
+        }
     }
 
-    pub fn keep_second(&self, state: u32, mut outcome: Outcome) -> u32 {
-        let state = State::decode(state);
-        let mut outcome_value = vec![0.0; max_outcome_encoding() + 1];
-        compute_outcome_values(state, &mut |i| self.get(i), &mut outcome_value);
-        compute_subset_expectations(&mut outcome_value);
-        choose_reroll(&mut outcome, &outcome_value);
-        outcome.encode()
+    #[test]
+    #[should_panic(expected = "More rerolls than the state has")]
+    fn keep_too_many_rerolls() {
+        let file = TempFile::new("keep_rerolls_test.bin");
+        let store = small_store(&file);
+        store.keep(0, Outcome::empty(), 3);
     }
 }
+
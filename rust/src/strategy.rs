@@ -0,0 +1,406 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::*;
+use crate::constants::*;
+
+/// A way of playing: which dice to keep and which row to choose. Any
+/// strategy can play a `Game` by `play_turn`.
+pub trait Strategy {
+    /// The dice to keep from `outcome` in `state` when `rerolls_left`
+    /// rerolls remain in the turn, which must be positive. Keeping every
+    /// die means the roll should be put on a row.
+    fn choose_keep(&mut self, state: State, outcome: Outcome, rerolls_left: usize) -> Outcome;
+
+    /// The row to put `outcome` on in `state`, one of `actions`. With
+    /// `RuleSet::max_saved_rerolls`, the `rerolls_left` are saved.
+    fn choose_row(&mut self, state: State, outcome: Outcome, rerolls_left: usize) -> Action;
+}
+
+/// Expected scores of states by their encoding, e.g. from a `Store` or a
+/// `LazyValue`.
+pub type StateValueFn = dyn Fn(u32) -> f64 + Sync;
+
+/// Play the turn of the current player of `game` with `strategy`,
+/// rolling the dice by `rng`. Returns the row chosen and its points.
+pub fn play_turn<S: Strategy + ?Sized, R: Rng>(game: &mut Game, strategy: &mut S, rng: &mut R) -> Result<(Action, u32)> {
+    let state = game.state();
+    let mut outcome = game.roll_random(rng)?;
+    while game.rerolls() > 0 {
+        let keep = strategy.choose_keep(state, outcome, game.rerolls());
+        if keep == outcome {
+            break;
+        }
+        game.keep(keep)?;
+        outcome = game.roll_random(rng)?;
+    }
+    let action = strategy.choose_row(state, outcome, game.rerolls());
+    let points = game.choose(action)?;
+    Ok((action, points))
+}
+
+/// Temperature of "softmax" in `strategy_by_name`.
+pub const DEFAULT_TEMPERATURE: f64 = 1.0;
+
+/// A name of `strategy_by_name` with its parameters.
+enum StrategyName {
+    Optimal,
+    Greedy,
+    Random,
+    Heuristic,
+    Softmax { temperature: f64, bias: f64 },
+}
+
+impl StrategyName {
+    fn parse(name: &str) -> Option<Self> {
+        let mut parts = name.split(':');
+        let name = parts.next()?;
+        let params = parts.map(|p| p.parse::<f64>().ok().filter(|x| x.is_finite() && *x >= 0.0)).collect::<Option<Vec<_>>>()?;
+        match (name, &params[..]) {
+            ("optimal", []) => Some(StrategyName::Optimal),
+            ("greedy", []) => Some(StrategyName::Greedy),
+            ("random", []) => Some(StrategyName::Random),
+            ("heuristic", []) => Some(StrategyName::Heuristic),
+            ("softmax", _) if params.len() <= 2 => Some(StrategyName::Softmax {
+                temperature: params.first().cloned().unwrap_or(DEFAULT_TEMPERATURE),
+                bias: params.get(1).cloned().unwrap_or(0.0),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The strategy of the given name: "optimal", "greedy", "random",
+/// "heuristic" or "softmax". The temperature and bias of "softmax" may
+/// follow, as in "softmax:2" or "softmax:2:0.5", and default to
+/// `DEFAULT_TEMPERATURE` and no bias. Only "optimal" and "softmax" use
+/// `state_value`, see `strategy_uses_state_value`, and only "random" and
+/// "softmax" use `seed`.
+pub fn strategy_by_name<'a>(name: &str, rules: &RuleSet, state_value: &'a (dyn Fn(u32) -> f64 + Sync), seed: u64) -> Option<Box<dyn Strategy + 'a>> {
+    let rules = rules.clone();
+    Some(match StrategyName::parse(name)? {
+        StrategyName::Optimal => Box::new(TableStrategy::new(rules, state_value)),
+        StrategyName::Greedy => Box::new(GreedyStrategy::new(rules)),
+        StrategyName::Random => Box::new(RandomStrategy::new(rules, StdRng::seed_from_u64(seed))),
+        StrategyName::Heuristic => Box::new(HeuristicStrategy::new(rules)),
+        StrategyName::Softmax { temperature, bias } => {
+            Box::new(SoftmaxStrategy::new(rules, state_value, temperature, bias, StdRng::seed_from_u64(seed)))
+        },
+    })
+}
+
+/// Whether the strategy `name` of `strategy_by_name` uses `state_value`,
+/// so that the table must be read, or None if there is no such strategy.
+pub fn strategy_uses_state_value(name: &str) -> Option<bool> {
+    StrategyName::parse(name).map(|s| matches!(s, StrategyName::Optimal | StrategyName::Softmax { .. }))
+}
+
+/// Maximizes the expected final score by a table of state values.
+pub struct TableStrategy<'a> {
+    rules: RuleSet,
+    state_value: &'a (dyn Fn(u32) -> f64 + Sync),
+    valuation: Valuation,
+}
+
+impl<'a> TableStrategy<'a> {
+    pub fn new(rules: RuleSet, state_value: &'a (dyn Fn(u32) -> f64 + Sync)) -> Self {
+        TableStrategy { rules, state_value, valuation: Valuation::new() }
+    }
+
+    fn set_state(&mut self, state: State) {
+        let state_value = self.state_value;
+        self.valuation.set_state(&self.rules, state, &mut |i| state_value(i));
+    }
+}
+
+impl Strategy for TableStrategy<'_> {
+    fn choose_keep(&mut self, state: State, outcome: Outcome, rerolls_left: usize) -> Outcome {
+        self.set_state(state);
+        self.valuation.best_keep(&self.rules, outcome, rerolls_left)
+    }
+
+    fn choose_row(&mut self, state: State, outcome: Outcome, rerolls_left: usize) -> Action {
+        self.set_state(state);
+        self.valuation.best_row(&self.rules, outcome, rerolls_left).expect("No rows left").0
+    }
+}
+
+/// Maximizes the expected points of the turn, without regard for the
+/// rows left for later turns.
+pub struct GreedyStrategy {
+    rules: RuleSet,
+    valuation: Valuation,
+}
+
+impl GreedyStrategy {
+    pub fn new(rules: RuleSet) -> Self {
+        GreedyStrategy { rules, valuation: Valuation::new() }
+    }
+}
+
+impl Strategy for GreedyStrategy {
+    fn choose_keep(&mut self, state: State, outcome: Outcome, rerolls_left: usize) -> Outcome {
+        self.valuation.set_state_by(&self.rules, state, &mut |_, points| points as f64);
+        self.valuation.best_keep(&self.rules, outcome, rerolls_left)
+    }
+
+    fn choose_row(&mut self, state: State, outcome: Outcome, _rerolls_left: usize) -> Action {
+        let mut best = None;
+        actions(&self.rules, state, outcome, |action, _next_state, points| {
+            if best.is_none_or(|(_, p)| points > p) {
+                best = Some((action, points));
+            }
+        });
+        best.expect("No rows left").0
+    }
+}
+
+/// Keeps each die with probability one half and chooses a row uniformly
+/// at random.
+pub struct RandomStrategy<R> {
+    rules: RuleSet,
+    rng: R,
+}
+
+impl<R: Rng> RandomStrategy<R> {
+    pub fn new(rules: RuleSet, rng: R) -> Self {
+        RandomStrategy { rules, rng }
+    }
+}
+
+impl<R: Rng> Strategy for RandomStrategy<R> {
+    fn choose_keep(&mut self, _state: State, outcome: Outcome, _rerolls_left: usize) -> Outcome {
+        let mut keep = Outcome::empty();
+        for (d, &count) in outcome.histogram.iter().enumerate() {
+            for _ in 0..count {
+                if self.rng.gen() {
+                    keep.histogram[d] += 1;
+                }
+            }
+        }
+        keep
+    }
+
+    fn choose_row(&mut self, state: State, outcome: Outcome, _rerolls_left: usize) -> Action {
+        let mut rows = Vec::new();
+        actions(&self.rules, state, outcome, |action, _next_state, _points| rows.push(action));
+        assert!(!rows.is_empty(), "No rows left");
+        rows[self.rng.gen_range(0, rows.len())]
+    }
+}
+
+/// Chooses keeps and rows at random, each with probability proportional
+/// to `exp(value / temperature)` where the value is its expected final
+/// score, like a player who usually sees the best choice but sometimes
+/// takes one that is almost as good, and rarely a much worse one. The
+/// value of a row is raised by `bias` times its points, favoring the
+/// rows that score the most now, as newcomers do. With temperature 0 the
+/// best choice is always taken, as by `TableStrategy` when the bias is 0.
+pub struct SoftmaxStrategy<'a, R> {
+    rules: RuleSet,
+    state_value: &'a (dyn Fn(u32) -> f64 + Sync),
+    valuation: Valuation,
+    temperature: f64,
+    bias: f64,
+    rng: R,
+}
+
+impl<'a, R: Rng> SoftmaxStrategy<'a, R> {
+    pub fn new(rules: RuleSet, state_value: &'a (dyn Fn(u32) -> f64 + Sync), temperature: f64, bias: f64, rng: R) -> Self {
+        assert!(temperature >= 0.0 && bias >= 0.0);
+        SoftmaxStrategy { rules, state_value, valuation: Valuation::new(), temperature, bias, rng }
+    }
+
+    /// One of `choices` with softmax probabilities of the values.
+    fn choose<T: Copy>(&mut self, choices: &[(T, f64)]) -> T {
+        let best = choices.iter().map(|c| c.1).fold(f64::NEG_INFINITY, f64::max);
+        if self.temperature == 0.0 {
+            return choices.iter().find(|c| c.1 == best).expect("Nothing to choose").0;
+        }
+        let weights: Vec<f64> = choices.iter().map(|c| ((c.1 - best) / self.temperature).exp()).collect();
+        let mut x = self.rng.gen::<f64>() * weights.iter().sum::<f64>();
+        for (c, w) in choices.iter().zip(weights) {
+            if x < w {
+                return c.0;
+            }
+            x -= w;
+        }
+        // Rounding may leave a tiny remainder.
+        choices[choices.len() - 1].0
+    }
+}
+
+impl<R: Rng> Strategy for SoftmaxStrategy<'_, R> {
+    fn choose_keep(&mut self, state: State, outcome: Outcome, rerolls_left: usize) -> Outcome {
+        let state_value = self.state_value;
+        self.valuation.set_state(&self.rules, state, &mut |i| state_value(i));
+        let keeps = self.valuation.rank_keeps(&self.rules, outcome, rerolls_left);
+        self.choose(&keeps)
+    }
+
+    fn choose_row(&mut self, state: State, outcome: Outcome, rerolls_left: usize) -> Action {
+        let saved = (rerolls_left as u32).min(self.rules.max_saved_rerolls);
+        let mut rows = Vec::new();
+        actions(&self.rules, state, outcome, |action, next_state, points| {
+            let value = (self.state_value)(next_state.with_saved_rerolls(saved).encode(&self.rules));
+            rows.push((action, value + (1.0 + self.bias) * points as f64));
+        });
+        self.choose(&rows)
+    }
+}
+
+/// Rows that score a fixed pattern, so that the roll is kept once it
+/// scores in one of them.
+const PATTERN_ROWS: [Comb; 12] = [S222, S33, R15, R26, R16, S23, YAHTZEE, FULL_HOUSE, SM_STRAIGHT, LG_STRAIGHT, YAHTZEE50, YATZY];
+/// Straight rows and the sides they need, bit `d` for side `d + 1`.
+const STRAIGHTS: [(Comb, u8); 8] = [
+    (R15, 0b011111), (R26, 0b111110), (R16, 0b111111),
+    (SM_STRAIGHT, 0b001111), (SM_STRAIGHT, 0b011110), (SM_STRAIGHT, 0b111100),
+    (LG_STRAIGHT, 0b011111), (LG_STRAIGHT, 0b111110),
+];
+
+/// Plays by simple rules of thumb, as a casual player might: stop when
+/// the roll scores in an open pattern row such as a straight or a full
+/// house, go for a straight when one is open and a side away, and
+/// otherwise keep the most common side. Rows are chosen for the most
+/// points above par, where the par of a side is its share of the bonus,
+/// the par of a pattern row is zero, and the par of other rows is half
+/// their most points.
+pub struct HeuristicStrategy {
+    rules: RuleSet,
+    max_points: [u32; COMB_COUNT],
+}
+
+impl HeuristicStrategy {
+    pub fn new(rules: RuleSet) -> Self {
+        let mut max_points = [0; COMB_COUNT];
+        for o in outcomes(&rules) {
+            comb_scores(&rules, o, |comb, points| max_points[comb] = max_points[comb].max(points));
+        }
+        HeuristicStrategy { rules, max_points }
+    }
+
+    fn open(&self, state: State, comb: Comb) -> bool {
+        self.rules.has_comb(comb) && !state.has_comb(comb)
+    }
+
+    fn par(&self, action: Action) -> f64 {
+        match action {
+            Action::Side(d) => (self.rules.bonus_limit as usize * (d + 1)) as f64 / (SIDES * (SIDES + 1) / 2) as f64,
+            Action::Combination(c) if PATTERN_ROWS.contains(&c) => 0.0,
+            Action::Combination(c) => self.max_points[c] as f64 / 2.0,
+        }
+    }
+}
+
+impl Strategy for HeuristicStrategy {
+    fn choose_keep(&mut self, state: State, outcome: Outcome, _rerolls_left: usize) -> Outcome {
+        let mut scores_pattern = false;
+        comb_scores(&self.rules, outcome, |comb, points| {
+            if points > 0 && PATTERN_ROWS.contains(&comb) && !state.has_comb(comb) {
+                scores_pattern = true;
+            }
+        });
+        if scores_pattern {
+            return outcome;
+        }
+        let rolled = (0..SIDES).filter(|&d| outcome.histogram[d] > 0).fold(0u8, |m, d| m | 1 << d);
+        for &(comb, sides) in STRAIGHTS.iter() {
+            if self.open(state, comb) && (sides & !rolled).count_ones() == 1 {
+                let mut keep = Outcome::empty();
+                for d in (0..SIDES).filter(|&d| sides & rolled & 1 << d != 0) {
+                    keep.histogram[d] = 1;
+                }
+                return keep;
+            }
+        }
+        // The most common side, preferring open sides and then high ones.
+        let d = (0..SIDES).max_by_key(|&d| (outcome.histogram[d], !state.has_side(d), d)).unwrap();
+        let mut keep = Outcome::empty();
+        keep.histogram[d] = outcome.histogram[d];
+        keep
+    }
+
+    fn choose_row(&mut self, state: State, outcome: Outcome, _rerolls_left: usize) -> Action {
+        let mut best = None;
+        actions(&self.rules, state, outcome, |action, _next_state, points| {
+            let value = points as f64 - self.par(action);
+            if best.is_none_or(|(_, v)| value > v) {
+                best = Some((action, value));
+            }
+        });
+        best.expect("No rows left").0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use crate::*;
+    use crate::constants::*;
+    use crate::testing;
+
+    fn rules() -> RuleSet {
+        RuleSet::new("small", 3, 2, 1, 10, &[S2, CHANCE, YAHTZEE])
+    }
+
+    /// Mean points of the strategy `name` over `games` seeded games.
+    fn mean_points(name: &str, games: u64) -> f64 {
+        let rules = rules();
+        let value = testing::state_value(&rules);
+        let state_value = |i: u32| value[i as usize];
+        let mut strategy = strategy_by_name(name, &rules, &state_value, 0).unwrap();
+        let mut total = 0;
+        for i in 0..games {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(i);
+            let mut game = Game::new(rules.clone(), 1);
+            while !game.done() {
+                play_turn(&mut game, &mut *strategy, &mut rng).unwrap();
+            }
+            total += game.player().points;
+        }
+        total as f64 / games as f64
+    }
+
+    #[test]
+    fn optimal_strategy() {
+        let rules = rules();
+        let expected = testing::state_value(&rules)[0] - rules.upper_par() as f64;
+        let mean = mean_points("optimal", 500);
+        assert!((mean - expected).abs() < 2.0, "{} != {}", mean, expected);
+    }
+
+    #[test]
+    fn other_strategies() {
+        let optimal = mean_points("optimal", 500);
+        for name in ["greedy", "random", "heuristic", "softmax"].iter() {
+            let mean = mean_points(name, 500);
+            assert!(mean < optimal, "{}: {} >= {}", name, mean, optimal);
+        }
+    }
+
+    #[test]
+    fn softmax_without_randomness() {
+        // Without randomness it is the optimal strategy.
+        assert_eq!(mean_points("softmax:0", 100), mean_points("optimal", 100));
+    }
+
+    #[test]
+    fn strategies_using_state_value() {
+        for name in ["optimal", "softmax", "softmax:0", "softmax:2:0.5"].iter() {
+            assert_eq!(strategy_uses_state_value(name), Some(true), "{}", name);
+        }
+        for name in ["greedy", "random", "heuristic"].iter() {
+            assert_eq!(strategy_uses_state_value(name), Some(false), "{}", name);
+        }
+        assert_eq!(strategy_uses_state_value("softmax:-1"), None);
+    }
+
+    #[test]
+    fn strategy_names() {
+        let rules = rules();
+        let state_value = |_: u32| 0.0;
+        for name in ["unknown", "optimal:1", "softmax:-1", "softmax:1:2:3"].iter() {
+            assert!(strategy_by_name(name, &rules, &state_value, 0).is_none());
+        }
+    }
+}
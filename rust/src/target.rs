@@ -0,0 +1,175 @@
+use crate::*;
+use crate::distribution::{write_vectors, VectorTable, VectorWriter};
+use crate::layer::solve_layers;
+use crate::utility::solve_vectors;
+
+const MAGIC: &[u8; 4] = b"YVT2";
+
+/// Probability of at least `t` more points according to `table`,
+/// where `table[t]` is that probability for `t` in range.
+fn at_least(table: &[f64], t: i64) -> f64 {
+    if t <= 0 {
+        1.0
+    } else if t as usize >= table.len() {
+        0.0
+    } else {
+        table[t as usize]
+    }
+}
+
+/// Points still needed from `points` to end with a final score of at
+/// least `target`. The final score is as shown by `State::display_score`,
+/// that is, relative to the par of the upper section if the rules say so.
+pub fn remaining_target(rules: &RuleSet, points: u32, target: i32) -> i64 {
+    target as i64 + rules.upper_par() as i64 - points as i64
+}
+
+/// For every number of points `t`, the probability of getting at least
+/// `t` more points from `state` when playing for exactly that.
+/// `target_value` must hold the vectors of the successors of `state`.
+pub fn state_target_value<D: StateVectors + ?Sized>(rules: &RuleSet, state: State, target_value: &D) -> Vec<f64> {
+    if state.done(rules) {
+        return vec![1.0];
+    }
+    let len = state.upper_bound_points(rules) as usize + 1;
+    let mut res = solve_vectors(rules, state, len, |next_state, points, t| {
+        at_least(target_value.vector(next_state), t as i64 - points as i64)
+    });
+    while res.last() == Some(&0.0) {
+        res.pop();
+    }
+    res
+}
+
+/// Compute `state_target_value` for every state. Every vector is
+/// returned in memory, so this is only practical for small rule sets
+/// such as the forced order variants. Use `compute_target_value_file`
+/// for the others.
+pub fn compute_target_value<P: FnMut(usize, usize)>(rules: &RuleSet, pi: P) -> Vec<Vec<f64>> {
+    let mut target_value = vec![Vec::new(); rules.state_count()];
+    solve_layers(rules, 0, None, |_, s, next| Some(state_target_value(rules, s, next)), |layer| {
+        for (i, v) in layer.iter() {
+            target_value[i as usize] = v.to_vec();
+        }
+        Ok(())
+    }, pi).unwrap();
+    target_value
+}
+
+/// Compute the target values as `compute_target_value` and write them to
+/// `path` in the format read by `TargetStore`. Only two layers of states
+/// are kept in memory, and the others are written when done.
+pub fn compute_target_value_file<P: FnMut(usize, usize)>(rules: &RuleSet, path: &str, pi: P) -> Result<()> {
+    let mut writer = VectorWriter::create(path, MAGIC, rules.state_count())?;
+    solve_layers(rules, 0, None, |_, s, next| Some(state_target_value(rules, s, next)), |layer| {
+        layer.iter().try_for_each(|(i, v)| writer.push(i, v))
+    }, pi)?;
+    writer.finish()
+}
+
+/// Write target values in the format read by `TargetStore`.
+pub fn write_target_value(path: &str, target_value: &[Vec<f64>]) -> Result<()> {
+    write_vectors(path, MAGIC, target_value)
+}
+
+/// Reader of the tables written by `write_target_value`.
+pub struct TargetStore {
+    table: VectorTable,
+}
+
+impl TargetStore {
+    pub fn new(path: &str) -> Result<TargetStore> {
+        Ok(TargetStore {
+            table: VectorTable::open(path, MAGIC)?,
+        })
+    }
+
+    pub fn len(&self) -> u32 {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Best probability of getting at least `t` more points from the state.
+    pub fn get(&self, s: u32, t: i64) -> Result<f64> {
+        let p = self.table.get_element(s, t.max(0) as usize, 0.0)?;
+        Ok(if t <= 0 { 1.0 } else { p })
+    }
+
+    /// The objective of getting at least `t` more points from the current
+    /// state, for use with `compute_roll_values_by` and `choose_keep_by`.
+    pub fn objective(&self, t: i64) -> impl FnMut(u32, u32) -> f64 + '_ {
+        move |next_state, points| self.get(next_state, t - points as i64).unwrap_or(f64::NAN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testing::{self, TempFile};
+
+    #[test]
+    fn target_value() {
+        let rules = testing::small_rules().forced();
+        let value = testing::state_value(&rules);
+        let distribution = compute_distribution(&rules, &mut |i| value[i as usize], |_, _| ());
+        let target_value = compute_target_value(&rules, |_, _| ());
+        for (d, w) in distribution.iter().zip(target_value.iter()) {
+            assert!((w[0] - 1.0).abs() < 1e-12);
+            // Playing for the target is at least as good as playing for
+            // expectation.
+            let mut at_least = 1.0;
+            for (t, p) in d.iter().enumerate() {
+                assert!(w.get(t).copied().unwrap_or(0.0) >= at_least - 1e-12);
+                at_least -= p;
+            }
+        }
+    }
+
+    #[test]
+    fn target_store() {
+        let rules = testing::small_rules().forced();
+        let target_value = compute_target_value(&rules, |_, _| ());
+        let file = TempFile::new("target_test.bin");
+        write_target_value(file.path(), &target_value).unwrap();
+        let store = TargetStore::new(file.path()).unwrap();
+        for t in -1..(target_value[0].len() as i64 + 1) {
+            let expected = if t <= 0 { 1.0 } else { target_value[0].get(t as usize).copied().unwrap_or(0.0) };
+            assert_eq!(store.get(0, t).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn target_file() {
+        let rules = testing::small_rules().forced();
+        let target_value = compute_target_value(&rules, |_, _| ());
+        let file = TempFile::new("target_file_test.bin");
+        compute_target_value_file(&rules, file.path(), |_, _| ()).unwrap();
+        let store = TargetStore::new(file.path()).unwrap();
+        for (i, w) in target_value.iter().enumerate() {
+            for t in 0..(w.len() as i64 + 1) {
+                assert_eq!(store.get(i as u32, t).unwrap(), if t == 0 { 1.0 } else { w.get(t as usize).copied().unwrap_or(0.0) });
+            }
+        }
+    }
+
+    #[test]
+    fn keep_for_target() {
+        let rules = testing::small_rules().forced();
+        let value = testing::state_value(&rules);
+        let file = TempFile::new("target_keep_test.bin");
+        compute_target_value_file(&rules, file.path(), |_, _| ()).unwrap();
+        let store = TargetStore::new(file.path()).unwrap();
+        // On the Chance row with 4 6 6, expectation keeps everything,
+        // but 17 points need another 5 or 6.
+        let state = State::all_sides().with_comb(constants::S2);
+        let roll = Outcome { histogram: [0, 0, 0, 1, 0, 2] };
+        let mut keep = roll;
+        choose_keep(&rules, state, &mut |i| value[i as usize], &mut keep, 1);
+        assert_eq!(keep, roll);
+        choose_keep_by(&rules, state, &mut store.objective(17), &mut keep, 1);
+        assert_eq!(keep, Outcome { histogram: [0, 0, 0, 0, 0, 2] });
+    }
+}
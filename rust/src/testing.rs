@@ -0,0 +1,70 @@
+//! Fixtures shared by the tests of the modules.
+use std::os::raw::c_uint;
+use std::sync::{Arc, Mutex};
+use crate::*;
+use crate::bridge::CError;
+
+/// Rules with few enough states to compute every table in a test: three
+/// dice, two rerolls, the upper section up to 10 points and the Pair and
+/// Chance rows.
+pub(crate) fn small_rules() -> RuleSet {
+    RuleSet::new("small", 3, 2, 1, 10, &[constants::S2, constants::CHANCE])
+}
+
+/// A file in the temporary directory, removed when dropped so that a
+/// failing test does not leave it behind. The name is prefixed by the
+/// name of the crate and the process id, so test runs do not share files.
+pub(crate) struct TempFile {
+    path: String,
+}
+
+impl TempFile {
+    pub(crate) fn new(name: &str) -> Self {
+        let name = format!("yahtzeevalue_{}_{}", std::process::id(), name);
+        TempFile { path: std::env::temp_dir().join(name).to_str().unwrap().to_owned() }
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// The code the C API gives for the error, if any.
+pub(crate) fn error_code<T>(r: Result<T>) -> Option<c_uint> {
+    r.err().map(|e| e.get_error_code())
+}
+
+/// The expected scores of every state of `rules`, computed once for all
+/// the tests that need them.
+pub(crate) fn state_value(rules: &RuleSet) -> Arc<Vec<f64>> {
+    cached(&STATE_VALUE, rules, || compute_state_value_threads(rules, 1, |_, _| ()))
+}
+
+/// The expected scores of the reachable states of `rules`, NaN for the
+/// others, computed once for all the tests that need them.
+pub(crate) fn reachable_state_value(rules: &RuleSet) -> Arc<Vec<f64>> {
+    cached(&REACHABLE_STATE_VALUE, rules, || compute_reachable_state_value(rules, 1, |_, _| ()))
+}
+
+type Cache = Mutex<Vec<(RuleSet, Arc<Vec<f64>>)>>;
+
+static STATE_VALUE: Cache = Mutex::new(Vec::new());
+static REACHABLE_STATE_VALUE: Cache = Mutex::new(Vec::new());
+
+fn cached<F: FnOnce() -> Vec<f64>>(cache: &Cache, rules: &RuleSet, compute: F) -> Arc<Vec<f64>> {
+    // Computed while holding the lock, so tests asking for the same rules
+    // wait for one computation instead of repeating it.
+    let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((_, value)) = cache.iter().find(|(r, _)| r == rules) {
+        return value.clone();
+    }
+    let value = Arc::new(compute());
+    cache.push((rules.clone(), value.clone()));
+    value
+}
@@ -0,0 +1,290 @@
+use crate::*;
+use crate::constants::*;
+use crate::layer::solve_layers;
+
+fn max_assign(dst: &mut Vec<f64>, src: &[f64]) {
+    if dst.is_empty() {
+        dst.extend_from_slice(src);
+    }
+    for (d, &s) in dst.iter_mut().zip(src.iter()) {
+        *d = d.max(s);
+    }
+}
+
+/// The best vector over the rows for the roll `o`, where `candidate`
+/// gives the vector of a row and `merge` keeps the better of two.
+fn stop_vector<C, M>(rules: &RuleSet, state: State, o: Outcome, saved_rerolls: u32, candidate: &mut C, merge: &mut M) -> Vec<f64>
+    where C: FnMut(u32, u32) -> Vec<f64>, M: FnMut(&mut Vec<f64>, &[f64])
+{
+    let mut best = Vec::new();
+    actions(rules, state, o, |_action, next_state, points| {
+        let i = next_state.with_saved_rerolls(saved_rerolls).encode(rules);
+        merge(&mut best, &candidate(i, points));
+    });
+    best
+}
+
+/// `compute_subset_expectations` for a table of vectors.
+pub(crate) fn compute_subset_averages(rules: &RuleSet, table: &mut [Vec<f64>]) {
+    let mut average = |o: Outcome| {
+        let mut res = vec![0.0; table[o.successors().next().unwrap().encode() as usize].len()];
+        for s in o.successors() {
+            for (r, &x) in res.iter_mut().zip(table[s.encode() as usize].iter()) {
+                *r += x;
+            }
+        }
+        for r in res.iter_mut() {
+            *r /= SIDES as f64;
+        }
+        table[o.encode() as usize] = res;
+    };
+    for n in (1..rules.dice_count).rev() {
+        for o in sub_outcomes(n) {
+            average(o);
+        }
+    }
+    average(Outcome::empty());
+}
+
+/// `compute_reroll_value` for a table of vectors.
+fn compute_best_subsets<M: FnMut(&mut Vec<f64>, &[f64])>(rules: &RuleSet, table: &mut [Vec<f64>], merge: &mut M) {
+    for n in 1..(rules.dice_count + 1) {
+        for o in sub_outcomes(n) {
+            let i = o.encode() as usize;
+            for p in o.predecessors() {
+                let best = table[p.encode() as usize].clone();
+                merge(&mut table[i], &best);
+            }
+        }
+    }
+}
+
+/// Like `compute_roll_values_by` for vectors of values: `candidate(next_state,
+/// points)` is the vector of a row, and `merge(a, b)` replaces `a` by
+/// the better of `a` and `b`, or by `b` if `a` is empty. Choices to keep
+/// dice are made the same way. Returns the average vector of `state`.
+fn solve_by<C, M>(rules: &RuleSet, state: State, mut candidate: C, mut merge: M) -> Vec<f64>
+    where C: FnMut(u32, u32) -> Vec<f64>, M: FnMut(&mut Vec<f64>, &[f64])
+{
+    let mut table = vec![Vec::new(); max_outcome_encoding(rules) + 1];
+    for o in outcomes(rules) {
+        table[o.encode() as usize] = stop_vector(rules, state, o, 0, &mut candidate, &mut merge);
+    }
+    compute_subset_averages(rules, &mut table);
+    for r in 1..(state.rerolls(rules) + 1) {
+        compute_best_subsets(rules, &mut table, &mut merge);
+        if rules.max_saved_rerolls > 0 {
+            let saved = (r as u32).min(rules.max_saved_rerolls);
+            for o in outcomes(rules) {
+                let stop = stop_vector(rules, state, o, saved, &mut candidate, &mut merge);
+                merge(&mut table[o.encode() as usize], &stop);
+            }
+        }
+        compute_subset_averages(rules, &mut table);
+    }
+    let mut res = Vec::new();
+    let mut denominator = 0;
+    for o in outcomes(rules) {
+        denominator += o.multiplicity();
+    }
+    for o in outcomes(rules) {
+        let weight = o.multiplicity() as f64 / denominator as f64;
+        let v = &table[o.encode() as usize];
+        res.resize(v.len(), 0.0);
+        for (r, &x) in res.iter_mut().zip(v.iter()) {
+            *r += weight * x;
+        }
+    }
+    res
+}
+
+/// Solve `len` objectives at once, each like `compute_roll_values_by`
+/// with `value(next_state, points, k)` as objective `k`. Returns the
+/// value of `state` for each objective.
+pub(crate) fn solve_vectors<V: FnMut(u32, u32, usize) -> f64>(rules: &RuleSet, state: State, len: usize, mut value: V) -> Vec<f64> {
+    solve_by(rules, state, |i, points| (0..len).map(|k| value(i, points, k)).collect(), max_assign)
+}
+
+/// Mean and second moment of the remaining points from `state` when
+/// every choice maximizes `Objective::MeanStd(lambda)`. `moments` must
+/// hold the moments of the successors of `state`.
+fn state_moments(rules: &RuleSet, state: State, lambda: f64, moments: &[[f64; 2]]) -> [f64; 2] {
+    if state.done(rules) {
+        return [0.0, 0.0];
+    }
+    let value = |v: &[f64]| Objective::mean_std_value(lambda, v[0], v[1]);
+    let res = solve_by(rules, state, |i, points| {
+        let [mean, second_moment] = moments[i as usize];
+        let p = points as f64;
+        vec![mean + p, second_moment + 2.0 * p * mean + p * p]
+    }, |dst, src| {
+        if dst.is_empty() || value(src) > value(dst) {
+            dst.clear();
+            dst.extend_from_slice(src);
+        }
+    });
+    [res[0], res[1]]
+}
+
+/// Compute the value of every state for `Objective::MeanStd(lambda)`.
+/// The choices depend on the spread of the later points, so unlike
+/// `compute_state_value` this keeps two moments of every state in memory
+/// and runs on a single thread.
+pub fn compute_mean_std_value<P: FnMut(usize, usize)>(rules: &RuleSet, lambda: f64, mut pi: P) -> Vec<f64> {
+    let states = rules.state_count();
+    pi(0, states);
+    let mut moments = vec![[0.0; 2]; states];
+    for i in (0..states).rev() {
+        let s = State::decode(rules, i as u32);
+        moments[i] = state_moments(rules, s, lambda, &moments);
+        if (states - i).is_multiple_of(1 << 10) {
+            pi(states - i, states);
+        }
+    }
+    moments.iter().map(|&[mean, second_moment]| Objective::mean_std_value(lambda, mean, second_moment)).collect()
+}
+
+/// For every number of points scored so far, the best expected utility
+/// of the final score from `state`. `utility[p]` is the utility of
+/// ending with `p` points, counted as in `actions`, and the last entry
+/// is used for more points. `utility_value` must hold the vectors of
+/// the successors of `state`.
+pub fn state_utility_value<D: StateVectors + ?Sized>(rules: &RuleSet, state: State, utility: &[f64], utility_value: &D) -> Vec<f64> {
+    if state.done(rules) {
+        return utility.to_vec();
+    }
+    let last = utility.len() - 1;
+    solve_vectors(rules, state, utility.len(), |next_state, points, p| {
+        utility_value.vector(next_state)[(p + points as usize).min(last)]
+    })
+}
+
+/// Compute `state_utility_value` for every state. The vectors are kept
+/// in memory, so this is only practical for small rule sets such as the
+/// forced order variants.
+pub fn compute_utility_value<P: FnMut(usize, usize)>(rules: &RuleSet, utility: &[f64], mut pi: P) -> Vec<Vec<f64>> {
+    let states = rules.state_count();
+    pi(0, states);
+    let mut utility_value = vec![Vec::new(); states];
+    for i in (0..states).rev() {
+        let s = State::decode(rules, i as u32);
+        utility_value[i] = state_utility_value(rules, s, utility, &utility_value);
+        if (states - i).is_multiple_of(1 << 10) {
+            pi(states - i, states);
+        }
+    }
+    utility_value
+}
+
+/// Compute `state_utility_value` for the successors of `state`, which
+/// is all that `utility_objective` needs in `state`. Only the states
+/// reachable from `state` are computed, and only two layers of them are
+/// kept in memory.
+pub fn compute_successor_utility_value<P: FnMut(usize, usize)>(rules: &RuleSet, state: State, utility: &[f64], pi: P) -> Layer {
+    if state.done(rules) {
+        return Layer::new();
+    }
+    let reachable = reachable_states_from(rules, state.encode(rules));
+    solve_layers(rules, state.turn_count() + 1, Some(&reachable), |_, s, next| {
+        Some(state_utility_value(rules, s, utility, next))
+    }, |_| Ok(()), pi).unwrap()
+}
+
+/// The objective of maximizing expected utility with `points` scored so
+/// far, for use with `compute_roll_values_by` and `choose_keep_by`.
+pub fn utility_objective<D: StateVectors + ?Sized>(utility_value: &D, points: u32) -> impl FnMut(u32, u32) -> f64 + '_ {
+    move |next_state, action_points| {
+        let v = utility_value.vector(next_state);
+        v[((points + action_points) as usize).min(v.len() - 1)]
+    }
+}
+
+/// Utility of each final number of points up to `len - 1` for beating
+/// an opponent, namely the probability of winning with ties counting
+/// half. The opponent has `opponent_points` and gets more points
+/// distributed as `opponent_distribution`, e.g. from `DistributionStore`.
+pub fn win_utility(opponent_points: u32, opponent_distribution: &[f64], len: usize) -> Vec<f64> {
+    let mut utility = vec![0.0; len];
+    let mut below = 0.0;
+    for (p, u) in utility.iter_mut().enumerate() {
+        let tie = match p.checked_sub(opponent_points as usize) {
+            Some(r) => opponent_distribution.get(r).copied().unwrap_or(0.0),
+            None => 0.0,
+        };
+        *u = below + 0.5 * tie;
+        below += tie;
+    }
+    utility
+}
+
+/// Utility of each final number of points up to `len - 1` for the
+/// expected score plus a bonus for passing thresholds: `thresholds`
+/// lists pairs of a number of points and the bonus for reaching it.
+pub fn threshold_utility(thresholds: &[(u32, f64)], len: usize) -> Vec<f64> {
+    (0..len).map(|p| {
+        let bonus: f64 = thresholds.iter().filter(|&&(t, _)| p as u32 >= t).map(|&(_, b)| b).sum();
+        p as f64 + bonus
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testing;
+
+    /// The chance of the small rules to beat an opponent playing for
+    /// expectation, with the length of the utility vectors.
+    fn win_value(rules: &RuleSet) -> (Vec<f64>, Vec<Vec<f64>>) {
+        let value = testing::state_value(rules);
+        let distribution = compute_distribution(rules, &mut |i| value[i as usize], |_, _| ());
+        let len = State::decode(rules, 0).upper_bound_points(rules) as usize + 1;
+        let utility = win_utility(0, &distribution[0], len);
+        let win_value = compute_utility_value(rules, &utility, |_, _| ());
+        (utility, win_value)
+    }
+
+    #[test]
+    fn linear_utility_value() {
+        // The identity as utility gives the expected score.
+        let rules = testing::small_rules().forced();
+        let value = testing::state_value(&rules);
+        let len = State::decode(&rules, 0).upper_bound_points(&rules) as usize + 1;
+        let linear: Vec<f64> = (0..len).map(|p| p as f64).collect();
+        let linear_value = compute_utility_value(&rules, &linear, |_, _| ());
+        assert!((linear_value[0][0] - value[0]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn win_chance() {
+        // Against an opponent playing for expectation, doing the same
+        // wins half of the time, so playing to win does at least as well.
+        let rules = testing::small_rules().forced();
+        let (_, win_value) = win_value(&rules);
+        assert!(win_value[0][0] >= 0.5 - 1e-12);
+        assert!(win_value[0][0] <= 1.0);
+    }
+
+    #[test]
+    fn successor_utility_value() {
+        // Solving from the start gives the same values for its successors.
+        let rules = testing::small_rules().forced();
+        let (utility, win_value) = win_value(&rules);
+        let successors = compute_successor_utility_value(&rules, State::decode(&rules, 0), &utility, |_, _| ());
+        assert!(!successors.is_empty());
+        for (s, v) in successors.iter() {
+            assert_eq!(State::decode(&rules, s).turn_count(), 1);
+            assert_eq!(v, &win_value[s as usize][..]);
+        }
+    }
+
+    #[test]
+    fn win_objective() {
+        let rules = testing::small_rules().forced();
+        let (_, win_value) = win_value(&rules);
+        let mut objective = utility_objective(&win_value, 0);
+        let mut outcome_value = Vec::new();
+        let mut scratch = Vec::new();
+        compute_roll_values_by(&rules, State::decode(&rules, 0), &mut objective, 1, &mut outcome_value, &mut scratch);
+        assert!(outcome_value.iter().all(|&w| (0.0..=1.0).contains(&w)));
+    }
+}
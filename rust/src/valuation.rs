@@ -1,23 +1,48 @@
-use std::mem;
+use std::{io, mem, thread};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::*;
 use crate::constants::*;
+use crate::layer::states_by_turn;
+use crate::utility::compute_subset_averages;
 
-pub fn compute_outcome_values<F: FnMut(u32) -> f64>(state: State, state_value: &mut F, outcome_value: &mut Vec<f64>) {
-    for o in outcomes() {
-        let mut best = 0f64;
-        actions(state, o, |_action, next_state, points| {
-            let i = next_state.encode();
-            let value = state_value(i) + points as f64;
-            best = best.max(value);
-        });
-        outcome_value[o.encode() as usize] = best;
+// The functions taking an `objective` closure work for any objective
+// to maximize. `objective(next_state, points)` is the value of scoring
+// `points` and moving to `next_state`, e.g. `state_value(next_state) +
+// points` for the expected score. The value tables they compute are
+// then maximized by `compute_reroll_value` and `choose_reroll`.
+
+pub fn compute_outcome_values<F: FnMut(u32) -> f64>(rules: &RuleSet, state: State, state_value: &mut F, outcome_value: &mut [f64]) {
+    compute_stop_values(rules, state, 0, &mut |i, points| state_value(i) + points as f64, outcome_value);
+}
+
+/// Best value of putting the roll `o` on a row, saving `saved_rerolls`
+/// rerolls for later turns.
+pub fn stop_value<F: FnMut(u32) -> f64>(rules: &RuleSet, state: State, o: Outcome, saved_rerolls: u32, state_value: &mut F) -> f64 {
+    stop_value_by(rules, state, o, saved_rerolls, &mut |i, points| state_value(i) + points as f64)
+}
+
+pub fn stop_value_by<O: FnMut(u32, u32) -> f64>(rules: &RuleSet, state: State, o: Outcome, saved_rerolls: u32, objective: &mut O) -> f64 {
+    let mut best = None;
+    actions(rules, state, o, |_action, next_state, points| {
+        let i = next_state.with_saved_rerolls(saved_rerolls).encode(rules);
+        let value = objective(i, points);
+        best = Some(best.map_or(value, |b: f64| b.max(value)));
+    });
+    // There are no rows left when the game is over.
+    best.unwrap_or(0.0)
+}
+
+fn compute_stop_values<O: FnMut(u32, u32) -> f64>(rules: &RuleSet, state: State, saved_rerolls: u32, objective: &mut O, outcome_value: &mut [f64]) {
+    for o in outcomes(rules) {
+        outcome_value[o.encode() as usize] = stop_value_by(rules, state, o, saved_rerolls, objective);
     }
 }
 
-pub fn compute_subset_expectations(outcome_value: &mut Vec<f64>) {
+pub fn compute_subset_expectations(rules: &RuleSet, outcome_value: &mut [f64]) {
     // Compute expected value when keeping a subset
-    for n in (1..DICE_COUNT).rev() {
+    for n in (1..rules.dice_count).rev() {
         for o in sub_outcomes(n) {
             let i = o.encode() as usize;
             outcome_value[i] = 0.0;
@@ -53,7 +78,9 @@ fn each_subset<F: FnMut(Outcome)>(outcome: Outcome, mut f: F) {
     each_subset_help(outcome, &mut f, 0);
 }
 
-pub fn choose_reroll(outcome: &mut Outcome, reroll_value: &Vec<f64>) {
+/// Replace `outcome` by the subset with the greatest value in
+/// `reroll_value`, which may be a table of any objective.
+pub fn choose_reroll(outcome: &mut Outcome, reroll_value: &[f64]) {
     let mut best = reroll_value[outcome.encode() as usize];
     each_subset(*outcome, |o| {
         let value = reroll_value[o.encode() as usize];
@@ -64,9 +91,29 @@ pub fn choose_reroll(outcome: &mut Outcome, reroll_value: &Vec<f64>) {
     });
 }
 
-fn compute_best_subset_values(best_subset_value: &mut Vec<f64>) {
+/// Every subset of `outcome` with its value, best first. Subsets of equal
+/// value are in the order `choose_reroll` considers them, so the first
+/// is the one it chooses, and subsets valued NaN come last.
+fn rank_subsets<V: FnMut(Outcome) -> f64>(outcome: Outcome, mut value: V) -> Vec<(Outcome, f64)> {
+    let mut res = vec![(outcome, value(outcome))];
+    each_subset(outcome, |o| {
+        if o != outcome {
+            res.push((o, value(o)));
+        }
+    });
+    res.sort_by(|a, b| a.1.is_nan().cmp(&b.1.is_nan()).then(b.1.total_cmp(&a.1)));
+    res
+}
+
+/// Every subset of `outcome` with its value in `reroll_value`, best
+/// first, so the first is the one `choose_reroll` chooses.
+pub fn rank_rerolls(outcome: Outcome, reroll_value: &[f64]) -> Vec<(Outcome, f64)> {
+    rank_subsets(outcome, |o| reroll_value[o.encode() as usize])
+}
+
+fn compute_best_subset_values(rules: &RuleSet, best_subset_value: &mut [f64]) {
     // Compute best expected value when keeping a subset
-    for n in 1..(DICE_COUNT + 1) {
+    for n in 1..(rules.dice_count + 1) {
         for o in sub_outcomes(n) {
             let i = o.encode() as usize;
             for p in o.predecessors() {
@@ -76,18 +123,18 @@ fn compute_best_subset_values(best_subset_value: &mut Vec<f64>) {
     }
 }
 
-pub fn compute_reroll_value(outcome_value: &Vec<f64>, best_subset_value: &mut Vec<f64>) {
+/// Compute the best value of keeping a subset of each roll, for the
+/// objective of the `outcome_value` table.
+pub fn compute_reroll_value(rules: &RuleSet, outcome_value: &[f64], best_subset_value: &mut Vec<f64>) {
     best_subset_value.resize(outcome_value.len(), 0.0);
-    for i in 0..outcome_value.len() {
-        best_subset_value[i] = outcome_value[i];
-    }
-    compute_best_subset_values(best_subset_value);
+    best_subset_value.copy_from_slice(outcome_value);
+    compute_best_subset_values(rules, best_subset_value);
 }
 
-fn expectation_over_outcomes(outcome_value: &Vec<f64>) -> f64 {
+fn expectation_over_outcomes(rules: &RuleSet, outcome_value: &[f64]) -> f64 {
     let mut numerator = 0.0;
     let mut denominator = 0;
-    for o in outcomes() {
+    for o in outcomes(rules) {
         let i = o.encode() as usize;
         let m = o.multiplicity();
         numerator += m as f64 * outcome_value[i];
@@ -96,47 +143,670 @@ fn expectation_over_outcomes(outcome_value: &Vec<f64>) -> f64 {
     numerator / denominator as f64
 }
 
-//pub struct Valuation {
-//    value_for_roll: [Vec<f64>; 3],
-//    current_state: u32,
-//}
-//
-//impl Valuation {
-//    pub fn new() -> Self {
-//        let outcome_value = vec![0.0; max_outcome_encoding() + 1];
-//        let best_subset_value = vec![0.0; outcome_value.len()];
-//        Valuation {
-//            outcome_value: outcome_value,
-//            best_subset_value: best_subset_value,
-//            current_state: -1i32 as u32,
-//        }
-//    }
-//}
-
-pub fn compute_state_value<F: FnMut(usize, usize)>(mut pi: F) -> Vec<f64> {
-    let states = (1 + BONUS_LIMIT as usize) << (SIDES + COMB_COUNT);
-    pi(0, states);
-    let mut state_value = vec![11111111111.0; states];
-    let mut outcome_value = vec![0.0; max_outcome_encoding() + 1];
-    let mut best_subset_value = Vec::new();
-    for i in (0..states).rev() {
-        let s = State::decode(i as u32);
-        compute_outcome_values(s, &mut |i| state_value[i as usize], &mut outcome_value);
-        compute_subset_expectations(&mut outcome_value);
+/// Compute the value of each roll when `rerolls` rerolls remain in the
+/// turn, and the expected value of keeping each subset and rolling the
+/// rest with `rerolls` rerolls remaining afterwards.
+pub fn compute_roll_values<F: FnMut(u32) -> f64>(rules: &RuleSet, state: State, state_value: &mut F, rerolls: usize,
+                                                 outcome_value: &mut Vec<f64>, scratch: &mut Vec<f64>) {
+    compute_roll_values_by(rules, state, &mut |i, points| state_value(i) + points as f64, rerolls, outcome_value, scratch);
+}
+
+pub fn compute_roll_values_by<O: FnMut(u32, u32) -> f64>(rules: &RuleSet, state: State, objective: &mut O, rerolls: usize,
+                                                        outcome_value: &mut Vec<f64>, scratch: &mut Vec<f64>) {
+    let mut stop = Vec::new();
+    for saved in 0..(saved_rerolls_after(rules, rerolls) + 1) {
+        let mut stop_saved = vec![0.0; max_outcome_encoding(rules) + 1];
+        compute_stop_values(rules, state, saved, objective, &mut stop_saved);
+        stop.push(stop_saved);
+    }
+    compute_roll_stages(rules, &stop, rerolls, outcome_value, scratch, |_, _| ());
+}
+
+/// Most rerolls that stopping can save when `rerolls` remain in the turn.
+pub(crate) fn saved_rerolls_after(rules: &RuleSet, rerolls: usize) -> u32 {
+    (rerolls as u32).min(rules.max_saved_rerolls)
+}
 
-        for _ in 0..REROLL_COUNT {
-            compute_reroll_value(&outcome_value, &mut best_subset_value);
-            compute_subset_expectations(&mut best_subset_value);
-            mem::swap(&mut outcome_value, &mut best_subset_value);
+/// The stages of `compute_roll_values_by`, given the value of putting
+/// each roll on a row while saving each possible number of rerolls.
+/// Calls `stage` with the table of each number of rerolls up to `rerolls`.
+fn compute_roll_stages<S: FnMut(usize, &[f64])>(rules: &RuleSet, stop: &[Vec<f64>], rerolls: usize,
+                                               outcome_value: &mut Vec<f64>, scratch: &mut Vec<f64>, mut stage: S) {
+    outcome_value.clear();
+    outcome_value.extend_from_slice(&stop[0]);
+    compute_subset_expectations(rules, outcome_value);
+    stage(0, outcome_value);
+
+    for r in 1..(rerolls + 1) {
+        compute_reroll_value(rules, outcome_value, scratch);
+        if rules.max_saved_rerolls > 0 {
+            // Stopping early saves the remaining rerolls.
+            let stop = &stop[saved_rerolls_after(rules, r) as usize];
+            for o in outcomes(rules) {
+                let i = o.encode() as usize;
+                scratch[i] = scratch[i].max(stop[i]);
+            }
         }
+        compute_subset_expectations(rules, scratch);
+        mem::swap(outcome_value, scratch);
+        stage(r, outcome_value);
+    }
+}
+
+/// Replace `outcome` by the best subset to keep when `rerolls` rerolls
+/// remain in the turn. Keeping every die means stopping.
+pub fn choose_keep<F: FnMut(u32) -> f64>(rules: &RuleSet, state: State, state_value: &mut F, outcome: &mut Outcome, rerolls: usize) {
+    choose_keep_by(rules, state, &mut |i, points| state_value(i) + points as f64, outcome, rerolls);
+}
+
+pub fn choose_keep_by<O: FnMut(u32, u32) -> f64>(rules: &RuleSet, state: State, objective: &mut O, outcome: &mut Outcome, rerolls: usize) {
+    if rerolls == 0 {
+        return;
+    }
+    let mut reroll_value = Vec::new();
+    let mut scratch = Vec::new();
+    compute_roll_values_by(rules, state, objective, rerolls - 1, &mut reroll_value, &mut scratch);
+    if rules.max_saved_rerolls > 0 {
+        let saved = (rerolls as u32).min(rules.max_saved_rerolls);
+        let i = outcome.encode() as usize;
+        reroll_value[i] = reroll_value[i].max(stop_value_by(rules, state, *outcome, saved, objective));
+    }
+    choose_reroll(outcome, &reroll_value);
+}
+
+/// Probabilities of ending a turn with a roll that scores in each row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HitProbabilities {
+    /// Indexed like `RuleSet::combs`.
+    pub combs: Vec<f64>,
+    /// Indexed by side, 0 for 1's.
+    pub sides: [f64; SIDES],
+}
 
-        state_value[i] = expectation_over_outcomes(&outcome_value);
-        if state_value[i] > 1000.0 {
-            panic!("State {} got expectation {}", s, state_value[i]);
+/// For each row, 1 if the roll `o` scores in it and 0 otherwise, with
+/// the combinations first as in `HitProbabilities`.
+fn hits(rules: &RuleSet, o: Outcome) -> Vec<f64> {
+    let mut res = vec![0.0; rules.comb_count() + SIDES];
+    comb_scores(rules, o, |comb, points| {
+        if points > 0 {
+            res[rules.comb_index(comb).unwrap()] = 1.0;
         }
-        if (states - i) % (1 << 10) == 0 {
-            pi(states - i, states);
+    });
+    for d in 0..SIDES {
+        if o.histogram[d] > 0 {
+            res[rules.comb_count() + d] = 1.0;
         }
     }
-    state_value
+    res
+}
+
+/// The value tables of one state for every number of rerolls left in
+/// the turn, from the final roll back to the initial roll. The tables
+/// are kept until the state changes, so every decision of a turn is
+/// answered from a single computation.
+#[derive(Default)]
+pub struct Valuation {
+    state: Option<u32>,
+    /// `roll_value[r]` is the value of each roll with `r` rerolls left,
+    /// and of keeping each subset and rolling the rest with `r` left after.
+    roll_value: Vec<Vec<f64>>,
+    /// `stop_value[saved]` is the value of putting each roll on the best
+    /// row, which is `best_row[saved]`, and saving `saved` rerolls.
+    stop_value: Vec<Vec<f64>>,
+    best_row: Vec<Vec<Option<(Action, u32, u32)>>>,
+    scratch: Vec<f64>,
 }
+
+impl Valuation {
+    pub fn new() -> Self {
+        Valuation::default()
+    }
+
+    /// Compute the tables of `state` for the expected score, unless they
+    /// are already computed.
+    pub fn set_state<F: FnMut(u32) -> f64>(&mut self, rules: &RuleSet, state: State, state_value: &mut F) {
+        self.set_state_by(rules, state, &mut |i, points| state_value(i) + points as f64);
+    }
+
+    /// Like `set_state` for any objective. Call `clear` first when the
+    /// objective changes without the state changing.
+    pub fn set_state_by<O: FnMut(u32, u32) -> f64>(&mut self, rules: &RuleSet, state: State, objective: &mut O) {
+        let encoded = state.encode(rules);
+        if self.state == Some(encoded) {
+            return;
+        }
+        self.state = None;
+        let rerolls = state.rerolls(rules);
+        self.stop_value.clear();
+        self.best_row.clear();
+        for saved in 0..(saved_rerolls_after(rules, rerolls) + 1) {
+            let mut stop = vec![0.0; max_outcome_encoding(rules) + 1];
+            let mut rows = vec![None; stop.len()];
+            for o in outcomes(rules) {
+                let i = o.encode() as usize;
+                let mut best = None;
+                actions(rules, state, o, |action, next_state, points| {
+                    let j = next_state.with_saved_rerolls(saved).encode(rules);
+                    let value = objective(j, points);
+                    if best.is_none_or(|b| value > b) {
+                        best = Some(value);
+                        rows[i] = Some((action, j, points));
+                    }
+                });
+                stop[i] = best.unwrap_or(0.0);
+            }
+            self.stop_value.push(stop);
+            self.best_row.push(rows);
+        }
+        let mut roll_value = mem::take(&mut self.roll_value);
+        roll_value.resize(rerolls + 1, Vec::new());
+        let mut outcome_value = Vec::new();
+        compute_roll_stages(rules, &self.stop_value, rerolls, &mut outcome_value, &mut self.scratch, |r, table| {
+            roll_value[r].clear();
+            roll_value[r].extend_from_slice(table);
+        });
+        self.roll_value = roll_value;
+        self.state = Some(encoded);
+    }
+
+    /// Forget the tables, so the next `set_state` computes them again.
+    pub fn clear(&mut self) {
+        self.state = None;
+    }
+
+    /// The encoding of the state of the tables.
+    pub fn state(&self) -> Option<u32> {
+        self.state
+    }
+
+    /// The value of the roll `outcome` when `rerolls` rerolls remain in
+    /// the turn. For fewer dice, the value of keeping them and rolling
+    /// the rest, with `rerolls` rerolls left after that.
+    pub fn roll_value(&self, outcome: Outcome, rerolls: usize) -> f64 {
+        self.roll_value[rerolls][outcome.encode() as usize]
+    }
+
+    /// The value of keeping `keep` and rolling the rest when `rerolls`
+    /// rerolls remain in the turn, which must be at least 1.
+    pub fn keep_value(&self, keep: Outcome, rerolls: usize) -> f64 {
+        self.roll_value(keep, rerolls - 1)
+    }
+
+    /// The best subset of `outcome` to keep when `rerolls` rerolls remain
+    /// in the turn, as in `choose_keep`. Keeping every die means stopping.
+    pub fn best_keep(&self, rules: &RuleSet, mut outcome: Outcome, rerolls: usize) -> Outcome {
+        if rerolls == 0 {
+            return outcome;
+        }
+        let reroll_value = &self.roll_value[rerolls - 1];
+        let roll = outcome;
+        let mut best = reroll_value[roll.encode() as usize];
+        if rules.max_saved_rerolls > 0 {
+            best = best.max(self.stop_value[saved_rerolls_after(rules, rerolls) as usize][roll.encode() as usize]);
+        }
+        each_subset(roll, |o| {
+            let value = reroll_value[o.encode() as usize];
+            if value > best {
+                best = value;
+                outcome = o;
+            }
+        });
+        outcome
+    }
+
+    /// Every subset of `outcome` to keep when `rerolls` rerolls remain in
+    /// the turn with its value, best first as chosen by `best_keep`.
+    /// Without rerolls, the only choice is to keep every die.
+    pub fn rank_keeps(&self, rules: &RuleSet, outcome: Outcome, rerolls: usize) -> Vec<(Outcome, f64)> {
+        if rerolls == 0 {
+            return vec![(outcome, self.roll_value(outcome, 0))];
+        }
+        let reroll_value = &self.roll_value[rerolls - 1];
+        let stop = if rules.max_saved_rerolls > 0 {
+            self.stop_value[saved_rerolls_after(rules, rerolls) as usize][outcome.encode() as usize]
+        } else {
+            f64::NEG_INFINITY
+        };
+        rank_subsets(outcome, |o| {
+            let value = reroll_value[o.encode() as usize];
+            if o == outcome { value.max(stop) } else { value }
+        })
+    }
+
+    /// The probability of ending the turn with a roll scoring in each row
+    /// when keeping `keep`, rolling the rest and then keeping the best
+    /// dice with `rerolls` rerolls left, as chosen by `best_keep`. `rerolls`
+    /// is at most the rerolls of the state.
+    pub fn hit_probabilities(&self, rules: &RuleSet, keep: Outcome, rerolls: usize) -> HitProbabilities {
+        let mut table = vec![Vec::new(); max_outcome_encoding(rules) + 1];
+        for o in outcomes(rules) {
+            table[o.encode() as usize] = hits(rules, o);
+        }
+        compute_subset_averages(rules, &mut table);
+        for r in 1..(rerolls + 1) {
+            let saved = saved_rerolls_after(rules, r) as usize;
+            let mut next = vec![Vec::new(); table.len()];
+            for o in outcomes(rules) {
+                let i = o.encode() as usize;
+                let k = self.best_keep(rules, o, r);
+                // Keeping every die stops if that saves rerolls.
+                let stop = rules.max_saved_rerolls > 0 && self.stop_value[saved][i] >= self.roll_value[r - 1][i];
+                next[i] = if k == o && stop { hits(rules, o) } else { table[k.encode() as usize].clone() };
+            }
+            compute_subset_averages(rules, &mut next);
+            table = next;
+        }
+        let res = &table[keep.encode() as usize];
+        let mut sides = [0.0; SIDES];
+        sides.copy_from_slice(&res[rules.comb_count()..]);
+        HitProbabilities {
+            combs: res[..rules.comb_count()].to_vec(),
+            sides,
+        }
+    }
+
+    /// The best row for the roll `outcome` when stopping with `rerolls`
+    /// rerolls left in the turn, as the action, the encoding of the next
+    /// state and the points. None if the game is over.
+    pub fn best_row(&self, rules: &RuleSet, outcome: Outcome, rerolls: usize) -> Option<(Action, u32, u32)> {
+        self.best_row[saved_rerolls_after(rules, rerolls) as usize][outcome.encode() as usize]
+    }
+}
+
+/// Number of states handed to a thread at a time.
+const BLOCK_SIZE: usize = 1 << 10;
+
+fn evaluate_state(rules: &RuleSet, objective: Objective, state_value: &[f64], i: u32,
+                  outcome_value: &mut Vec<f64>, scratch: &mut Vec<f64>) -> f64 {
+    let s = State::decode(rules, i);
+    let value = match objective {
+        Objective::Expectation => expected_state_value(rules, s, &mut |j| state_value[j as usize], outcome_value, scratch),
+        Objective::Exponential(_) if s.done(rules) => 0.0,
+        Objective::Exponential(a) => {
+            // Maximize expected utility, which is the same as maximizing
+            // the certainty equivalent of each choice.
+            let mut utility = |j: u32, points| Objective::exponential_utility(a, state_value[j as usize] + points as f64);
+            compute_roll_values_by(rules, s, &mut utility, s.rerolls(rules), outcome_value, scratch);
+            Objective::exponential_certainty_equivalent(a, expectation_over_outcomes(rules, outcome_value))
+        },
+        Objective::MeanStd(_) => panic!("Mean-std values are computed by compute_mean_std_value"),
+    };
+    if value > 1000.0 {
+        panic!("State {} got expectation {}", s.display(rules), value);
+    }
+    value
+}
+
+/// The expected score from `state` given that of its successors.
+pub(crate) fn expected_state_value<F: FnMut(u32) -> f64>(rules: &RuleSet, state: State, state_value: &mut F,
+                                                         outcome_value: &mut Vec<f64>, scratch: &mut Vec<f64>) -> f64 {
+    compute_roll_values(rules, state, state_value, state.rerolls(rules), outcome_value, scratch);
+    expectation_over_outcomes(rules, outcome_value)
+}
+
+/// Evaluate blocks of a layer until there are none left. Returns after
+/// a single block if `once` is set, and false if there was no block.
+fn evaluate_blocks<'a, I>(rules: &RuleSet, objective: Objective, state_value: &[f64], blocks: &Mutex<I>,
+                          done: &AtomicUsize, once: bool) -> bool
+    where I: Iterator<Item = (&'a [u32], &'a mut [f64])>
+{
+    let mut outcome_value = Vec::new();
+    let mut scratch = Vec::new();
+    loop {
+        let next = blocks.lock().unwrap().next();
+        let (indices, values) = match next {
+            Some(block) => block,
+            None => return false,
+        };
+        for (&i, v) in indices.iter().zip(values.iter_mut()) {
+            *v = evaluate_state(rules, objective, state_value, i, &mut outcome_value, &mut scratch);
+        }
+        done.fetch_add(indices.len(), Ordering::SeqCst);
+        if once {
+            return true;
+        }
+    }
+}
+
+/// Compute the expected score of optimal play from every state,
+/// using all available cores.
+pub fn compute_state_value<F: FnMut(usize, usize)>(rules: &RuleSet, pi: F) -> Vec<f64> {
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    compute_state_value_threads(rules, threads, pi)
+}
+
+/// Compute state values on `threads` threads, one layer of states with
+/// equal `turn_count` at a time. Every successor of a state is in the
+/// next layer, so the states of a layer can be evaluated in any order,
+/// and the result does not depend on the number of threads.
+pub fn compute_state_value_threads<F: FnMut(usize, usize)>(rules: &RuleSet, threads: usize, pi: F) -> Vec<f64> {
+    solve(rules, Objective::Expectation, threads, None, None, |_, _, _| Ok(()), pi).unwrap()
+}
+
+/// Compute the value of every state for `objective` on `threads` threads,
+/// see `Objective`. `Objective::Expectation` gives `compute_state_value_threads`.
+pub fn compute_objective_value<F: FnMut(usize, usize)>(rules: &RuleSet, objective: Objective, threads: usize, pi: F) -> Vec<f64> {
+    match objective {
+        Objective::MeanStd(lambda) => compute_mean_std_value(rules, lambda, pi),
+        _ => solve(rules, objective, threads, None, None, |_, _, _| Ok(()), pi).unwrap(),
+    }
+}
+
+/// Like `compute_state_value_threads`, but only evaluates the states
+/// reachable from `State::initial()`. The other states are NaN.
+pub fn compute_reachable_state_value<F: FnMut(usize, usize)>(rules: &RuleSet, threads: usize, pi: F) -> Vec<f64> {
+    let reachable = reachable_states(rules);
+    solve(rules, Objective::Expectation, threads, Some(&reachable), None, |_, _, _| Ok(()), pi).unwrap()
+}
+
+/// Compute state values for `objective` as above, writing a `Checkpoint`
+/// to `path` after each layer. With `resume`, the computation continues
+/// from the checkpoint in `path`, giving the same result as an
+/// uninterrupted run. `Objective::MeanStd` is computed without checkpoints.
+pub fn compute_state_value_checkpointed<F: FnMut(usize, usize)>(rules: &RuleSet, objective: Objective, threads: usize,
+                                                               reachable_only: bool, path: &str, resume: bool,
+                                                               pi: F) -> Result<Vec<f64>> {
+    if let Objective::MeanStd(lambda) = objective {
+        return Ok(compute_mean_std_value(rules, lambda, pi));
+    }
+    let start = if resume { Some(Checkpoint::read(path, rules)?) } else { None };
+    if start.as_ref().is_some_and(|c| c.reachable_only != reachable_only) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Checkpoint has different reachable mode").into());
+    }
+    if start.as_ref().is_some_and(|c| c.objective != objective) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Checkpoint is for a different objective").into());
+    }
+    let reachable = if reachable_only { Some(reachable_states(rules)) } else { None };
+    solve(rules, objective, threads, reachable.as_deref(), start, |turn, done, state_value| {
+        Checkpoint::write(path, rules, objective, turn, done, reachable_only, state_value)
+    }, pi)
+}
+
+/// Mark the states reachable from `State::initial()`.
+pub fn reachable_states(rules: &RuleSet) -> Vec<bool> {
+    reachable_states_from(rules, State::initial().encode(rules))
+}
+
+/// Mark the states reachable from the state with encoding `start`,
+/// including itself.
+pub fn reachable_states_from(rules: &RuleSet, start: u32) -> Vec<bool> {
+    let mut reachable = vec![false; rules.state_count()];
+    reachable[start as usize] = true;
+    for i in start as usize..reachable.len() {
+        if !reachable[i] {
+            continue;
+        }
+        let s = State::decode(rules, i as u32);
+        // Stopping before the last roll saves the remaining rerolls.
+        let max_saved = (s.rerolls(rules) as u32).min(rules.max_saved_rerolls);
+        for o in outcomes(rules) {
+            actions(rules, s, o, |_action, next_state, _points| {
+                for saved in 0..max_saved + 1 {
+                    let j = next_state.with_saved_rerolls(saved).encode(rules) as usize;
+                    debug_assert!(j > i);
+                    reachable[j] = true;
+                }
+            });
+        }
+    }
+    reachable
+}
+
+/// Evaluate the layers below `start`, calling `layer_done` with the
+/// values so far after each layer.
+fn solve<F, C>(rules: &RuleSet, objective: Objective, threads: usize, reachable: Option<&[bool]>, start: Option<Checkpoint>,
+               mut layer_done: C, mut pi: F) -> Result<Vec<f64>>
+    where F: FnMut(usize, usize), C: FnMut(u32, usize, &[f64]) -> Result<()>
+{
+    let states = match reachable {
+        Some(r) => r.iter().filter(|&&r| r).count(),
+        None => rules.state_count(),
+    };
+    pi(0, states);
+    let initial = if reachable.is_some() { f64::NAN } else { 11111111111.0 };
+    let (start_turn, start_done, mut state_value) = match start {
+        Some(c) => (c.turn, c.done, c.state_value),
+        None => (rules.turns() + 1, 0, vec![initial; rules.state_count()]),
+    };
+    let mut reported = 0;
+    let done = AtomicUsize::new(start_done);
+    // Report progress in steps of BLOCK_SIZE.
+    let report = |done: usize, reported: &mut usize, pi: &mut F| {
+        while *reported + BLOCK_SIZE <= done {
+            *reported += BLOCK_SIZE;
+            pi(*reported, states);
+        }
+    };
+    report(start_done, &mut reported, &mut pi);
+    let mut layers = states_by_turn(rules, 0, reachable);
+    for turn in (0..start_turn).rev() {
+        let layer = mem::take(&mut layers[turn as usize]);
+        let mut layer_value = vec![0.0; layer.len()];
+        {
+            let blocks = Mutex::new(layer.chunks(BLOCK_SIZE).zip(layer_value.chunks_mut(BLOCK_SIZE)));
+            let state_value = &state_value;
+            thread::scope(|scope| {
+                for _ in 1..threads {
+                    scope.spawn(|| evaluate_blocks(rules, objective, state_value, &blocks, &done, false));
+                }
+                // The progress callback runs on this thread between blocks.
+                while evaluate_blocks(rules, objective, state_value, &blocks, &done, true) {
+                    report(done.load(Ordering::SeqCst), &mut reported, &mut pi);
+                }
+            });
+        }
+        report(done.load(Ordering::SeqCst), &mut reported, &mut pi);
+        for (&i, &v) in layer.iter().zip(layer_value.iter()) {
+            state_value[i as usize] = v;
+        }
+        layer_done(turn, done.load(Ordering::SeqCst), &state_value)?;
+    }
+    if reported != states {
+        pi(states, states);
+    }
+    Ok(state_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::constants::*;
+    use crate::testing;
+
+    #[test]
+    fn forced_order_value() {
+        let rules = RuleSet::yatzy();
+        let value = compute_state_value(&rules.forced(), |_, _| ());
+        assert_eq!(value.len(), 1024);
+        assert!((value[0] - 122.5585).abs() < 1e-4);
+    }
+
+    #[test]
+    fn saved_rerolls_value() {
+        let rules = RuleSet::maxi_yatzy();
+        let last = State::all_sides().with_score(rules.bonus_limit);
+        let last = State { combination_mask: rules.comb_mask() & !(1 << YAHTZEE), ..last };
+        let mut value = Vec::new();
+        let mut scratch = Vec::new();
+        let mut expectation = |s: State| {
+            compute_roll_values(&rules, s, &mut |_| 0.0, s.rerolls(&rules), &mut value, &mut scratch);
+            value[0]
+        };
+        let e0 = expectation(last);
+        let e3 = expectation(last.with_saved_rerolls(3));
+        assert!(e0 > 0.0);
+        assert!(e3 > e0);
+    }
+
+    #[test]
+    fn parallel_state_value() {
+        let rules = RuleSet::yahtzee().forced();
+        let serial = compute_state_value_threads(&rules, 1, |_, _| ());
+        let mut progress = Vec::new();
+        let parallel = compute_state_value_threads(&rules, 3, |i, _| progress.push(i));
+        assert_eq!(serial.iter().map(|v| v.to_bits()).collect::<Vec<_>>(),
+                   parallel.iter().map(|v| v.to_bits()).collect::<Vec<_>>());
+        assert_eq!(progress, vec![0, 1024, rules.state_count()]);
+    }
+
+    #[test]
+    fn reachable_state_value() {
+        let rules = RuleSet::yahtzee().forced();
+        let full = testing::state_value(&rules);
+        let reachable = reachable_states(&rules);
+        let value = testing::reachable_state_value(&rules);
+        for i in 0..rules.state_count() {
+            if reachable[i] {
+                assert_eq!(value[i].to_bits(), full[i].to_bits());
+            } else {
+                assert!(value[i].is_nan());
+            }
+        }
+    }
+
+    #[test]
+    fn unreachable_states() {
+        let rules = RuleSet::yahtzee().forced();
+        let reachable = reachable_states(&rules);
+        assert!(reachable[0]);
+        // Twelve 1's are impossible before the 2's
+        assert!(!reachable[State::initial().with_side(0).with_score(12).encode(&rules) as usize]);
+    }
+
+    #[test]
+    fn objective_value() {
+        let rules = testing::small_rules().forced();
+        let value = testing::state_value(&rules);
+        let same = compute_objective_value(&rules, Objective::Expectation, 1, |_, _| ());
+        assert_eq!(same, *value);
+        let neutral = compute_objective_value(&rules, Objective::MeanStd(0.0), 1, |_, _| ());
+        let cautious = compute_objective_value(&rules, Objective::MeanStd(1.0), 1, |_, _| ());
+        assert!((neutral[0] - value[0]).abs() < 1e-9);
+        assert!(cautious[0] < value[0]);
+    }
+
+    #[test]
+    fn exponential_objective_value() {
+        // The certainty equivalent agrees with maximizing the utility of
+        // the final score, and is below the expectation when risk averse.
+        let rules = testing::small_rules().forced();
+        let value = testing::state_value(&rules);
+        let a = 0.1;
+        let exponential = compute_objective_value(&rules, Objective::Exponential(a), 1, |_, _| ());
+        assert!(exponential[0] < value[0]);
+        let len = State::decode(&rules, 0).upper_bound_points(&rules) as usize + 1;
+        let utility: Vec<f64> = (0..len).map(|p| Objective::exponential_utility(a, p as f64)).collect();
+        let utility_value = compute_utility_value(&rules, &utility, |_, _| ());
+        assert!((Objective::exponential_certainty_equivalent(a, utility_value[0][0]) - exponential[0]).abs() < 1e-9);
+    }
+
+    /// Call `f` with the valuation of every 17th state of the small rules
+    /// with saved rerolls, for every roll and number of rerolls left.
+    fn for_each_roll<F: FnMut(&RuleSet, &[f64], &mut Valuation, State, Outcome, usize)>(mut f: F) {
+        let mut rules = testing::small_rules();
+        rules.max_saved_rerolls = 2;
+        let rules = rules.forced();
+        let value = testing::state_value(&rules);
+        let mut valuation = Valuation::new();
+        for i in (0..rules.state_count() as u32).step_by(17) {
+            let state = State::decode(&rules, i);
+            if state.done(&rules) {
+                continue;
+            }
+            valuation.set_state(&rules, state, &mut |j| value[j as usize]);
+            assert_eq!(valuation.state(), Some(i));
+            for rerolls in 0..(state.rerolls(&rules) + 1) {
+                for o in outcomes(&rules) {
+                    f(&rules, &value, &mut valuation, state, o, rerolls);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn valuation_best_keep() {
+        for_each_roll(|rules, value, valuation, state, o, rerolls| {
+            let mut keep = o;
+            choose_keep(rules, state, &mut |j| value[j as usize], &mut keep, rerolls);
+            assert_eq!(valuation.best_keep(rules, o, rerolls), keep);
+        });
+    }
+
+    #[test]
+    fn valuation_rank_keeps() {
+        for_each_roll(|rules, value, valuation, state, o, rerolls| {
+            let mut keep = o;
+            choose_keep(rules, state, &mut |j| value[j as usize], &mut keep, rerolls);
+            let ranked = valuation.rank_keeps(rules, o, rerolls);
+            assert_eq!(ranked[0].0, keep);
+            assert!(ranked.windows(2).all(|w| w[0].1 >= w[1].1));
+            if rerolls > 0 {
+                let subsets: usize = o.histogram.iter().map(|&h| h as usize + 1).product();
+                assert_eq!(ranked.len(), subsets);
+            }
+        });
+    }
+
+    #[test]
+    fn valuation_roll_value() {
+        let mut roll_value = Vec::new();
+        let mut scratch = Vec::new();
+        for_each_roll(|rules, value, valuation, state, o, rerolls| {
+            compute_roll_values(rules, state, &mut |j| value[j as usize], rerolls, &mut roll_value, &mut scratch);
+            assert_eq!(valuation.roll_value(o, rerolls), roll_value[o.encode() as usize]);
+        });
+    }
+
+    #[test]
+    fn valuation_best_row() {
+        for_each_roll(|rules, value, valuation, state, o, rerolls| {
+            let (_, next_state, points) = valuation.best_row(rules, o, rerolls).unwrap();
+            let saved = (rerolls as u32).min(rules.max_saved_rerolls);
+            let best = stop_value(rules, state, o, saved, &mut |j| value[j as usize]);
+            assert_eq!(value[next_state as usize] + points as f64, best);
+        });
+    }
+
+    #[test]
+    fn hit_probabilities() {
+        let rules = RuleSet::yatzy();
+        let yatzy = rules.comb_index(YATZY).unwrap();
+        let mut valuation = Valuation::new();
+        // Any objective will do for rolls without rerolls.
+        valuation.set_state_by(&rules, State::initial(), &mut |_, points| points as f64);
+        let p = valuation.hit_probabilities(&rules, Outcome::empty(), 0);
+        assert!((p.combs[yatzy] - 6.0 / 7776.0).abs() < 1e-12);
+        assert!((p.sides[0] - (1.0 - (5.0f64 / 6.0).powi(5))).abs() < 1e-12);
+        assert!(p.combs.iter().chain(p.sides.iter()).all(|&x| (0.0..=1.0).contains(&x)));
+    }
+
+    #[test]
+    fn hit_probabilities_with_rerolls() {
+        let rules = RuleSet::yatzy();
+        let yatzy = rules.comb_index(YATZY).unwrap();
+        let mut valuation = Valuation::new();
+        valuation.set_state_by(&rules, State::initial(), &mut |_, points| points as f64);
+        let fives = Outcome { histogram: [0, 0, 0, 0, 4, 0] };
+        let p = valuation.hit_probabilities(&rules, fives, 0);
+        assert!((p.combs[yatzy] - 1.0 / 6.0).abs() < 1e-12);
+        assert_eq!(p.sides[4], 1.0);
+        // Keeping the four 5's gives Yatzy in three tries.
+        let p = valuation.hit_probabilities(&rules, fives, 2);
+        assert!((p.combs[yatzy] - (1.0 - (5.0f64 / 6.0).powi(3))).abs() < 1e-12);
+        assert!(p.combs.iter().chain(p.sides.iter()).all(|&x| (0.0..=1.0).contains(&x)));
+    }
+
+    #[test]
+    fn rank_rerolls_with_nan() {
+        // Values of states missing from a reachable-only table are NaN.
+        let roll = Outcome { histogram: [1, 0, 0, 0, 0, 1] };
+        let mut reroll_value = vec![0.0; max_outcome_encoding(&RuleSet::yatzy()) + 1];
+        reroll_value[Outcome { histogram: [1, 0, 0, 0, 0, 0] }.encode() as usize] = f64::NAN;
+        reroll_value[Outcome { histogram: [0, 0, 0, 0, 0, 1] }.encode() as usize] = 1.0;
+        let ranked = rank_rerolls(roll, &reroll_value);
+        assert_eq!(ranked.len(), 4);
+        assert_eq!(ranked[0], (Outcome { histogram: [0, 0, 0, 0, 0, 1] }, 1.0));
+        assert!(ranked[3].1.is_nan());
+    }
+}
+
@@ -1,8 +1,10 @@
+import collections
+
 from ._native import lib as _lib, ffi as _ffi
 from . import _bridge
 
 
-__all__ = ["YahtzeeError", "YahtzeeValue"]
+__all__ = ["YahtzeeError", "YahtzeeValue", "RankedAction"]
 
 _lib.yahtzeevalue_init()
 
@@ -31,12 +33,57 @@ class GameOverError(YahtzeeError):
     pass
 
 
+class UnreachableError(YahtzeeError):
+    pass
+
+
+class BadMagicError(YahtzeeError):
+    pass
+
+
+class RuleMismatchError(YahtzeeError):
+    pass
+
+
+class CorruptError(YahtzeeError):
+    pass
+
+
+class WrongPhaseError(YahtzeeError):
+    pass
+
+
+class IllegalRollError(YahtzeeError):
+    pass
+
+
+class IllegalKeepError(YahtzeeError):
+    pass
+
+
+class IllegalRowError(YahtzeeError):
+    pass
+
+
+class UnsupportedVersionError(YahtzeeError):
+    pass
+
+
 _special_errors = {
     1: UnicodeDecodeError,
     2: RangeError,
     3: IOError,
     4: FileNotFoundError,
     5: GameOverError,
+    6: UnreachableError,
+    7: BadMagicError,
+    8: RuleMismatchError,
+    9: CorruptError,
+    10: WrongPhaseError,
+    11: IllegalRollError,
+    12: IllegalKeepError,
+    13: IllegalRowError,
+    14: UnsupportedVersionError,
 }
 
 _rustcall = _bridge.make_rustcall(
@@ -44,6 +91,12 @@ _rustcall = _bridge.make_rustcall(
 )
 
 
+# A row a roll can be put on: the score sheet position as returned by
+# Database.best_action, the points, the state after and points plus the
+# value of that state.
+RankedAction = collections.namedtuple("RankedAction", "action points next_state total")
+
+
 def encode_roll(roll):
     histogram = [0] * 6
     for v in roll:
@@ -76,14 +129,72 @@ class Database:
         _rustcall(_lib.yahtzeevalue_unload, self._handle)
         del self._handle
 
+    def verify(self):
+        _rustcall(_lib.yahtzeevalue_verify, self._handle)
+
     def lookup(self, state):
         return _rustcall(_lib.yahtzeevalue_lookup, self._handle, state)
 
-    def best_action(self, state, histogram):
-        return _rustcall(_lib.yahtzeevalue_best_action, self._handle, state, encode_roll(histogram))
+    def best_action(self, state, histogram, rerolls=0):
+        return _rustcall(_lib.yahtzeevalue_best_action, self._handle, state, encode_roll(histogram), rerolls)
 
     def keep_first(self, state, histogram):
         return decode_roll(_rustcall(_lib.yahtzeevalue_keep_first, self._handle, state, encode_roll(histogram)))
 
     def keep_second(self, state, histogram):
         return decode_roll(_rustcall(_lib.yahtzeevalue_keep_second, self._handle, state, encode_roll(histogram)))
+
+    def keep(self, state, histogram, rerolls):
+        return decode_roll(_rustcall(_lib.yahtzeevalue_keep, self._handle, state, encode_roll(histogram), rerolls))
+
+    def rank_actions(self, state, histogram, rerolls=0):
+        capacity = 32
+        while True:
+            out = _ffi.new("struct yahtzeevalue_ranked_action[]", capacity)
+            n = _rustcall(_lib.yahtzeevalue_rank_actions, self._handle, state, encode_roll(histogram), rerolls, out, capacity)
+            if n <= capacity:
+                break
+            capacity = n
+        return [RankedAction(a.action, a.points, a.next_state, a.total) for a in out[0:n]]
+
+
+class Game:
+    """A game of the named rules. Moves that are not allowed raise
+    WrongPhaseError, IllegalRollError, IllegalKeepError or IllegalRowError
+    and leave the game as it was."""
+
+    def __init__(self, rules="super-yahtzee", players=1):
+        self._rules = rules
+        self._players = players
+
+    def __enter__(self):
+        self._handle = _rustcall(_lib.yahtzeevalue_game_new, self._rules.encode('utf-8'), self._players)
+        return self
+
+    def __exit__(self, exc_type, exc_value, exc_tb):
+        _rustcall(_lib.yahtzeevalue_game_free, self._handle)
+        del self._handle
+
+    def roll(self, histogram):
+        _rustcall(_lib.yahtzeevalue_game_roll, self._handle, encode_roll(histogram))
+
+    def keep(self, histogram):
+        _rustcall(_lib.yahtzeevalue_game_keep, self._handle, encode_roll(histogram))
+
+    def choose(self, action):
+        return _rustcall(_lib.yahtzeevalue_game_choose, self._handle, action)
+
+    def done(self):
+        return bool(_rustcall(_lib.yahtzeevalue_game_done, self._handle))
+
+    def player(self):
+        return _rustcall(_lib.yahtzeevalue_game_player, self._handle)
+
+    def state(self):
+        return _rustcall(_lib.yahtzeevalue_game_state, self._handle)
+
+    def rerolls(self):
+        return _rustcall(_lib.yahtzeevalue_game_rerolls, self._handle)
+
+    def points(self, player):
+        return _rustcall(_lib.yahtzeevalue_game_points, self._handle, player)